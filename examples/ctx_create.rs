@@ -1,6 +1,7 @@
-use cream::context::CreateFromContext;
 // Just check all compiles
-#[allow(unused, dead_code)]
+#![allow(unused)]
+
+use cream::context::CreateFromContext;
 use cream::context::{Context, FromContext};
 
 struct Ctx;
@@ -21,6 +22,73 @@ struct Deps {
     dep: Dep,
 }
 
+struct OtherCtx;
+
+impl Context for OtherCtx {}
+
+/// Available from both contexts
+#[derive(FromContext)]
+#[allow(dead_code)]
+#[context(Ctx, OtherCtx)]
+struct Shared {
+    #[context(default = 1)]
+    version: u8,
+}
+
+struct Settings(String);
+
+impl From<Dep> for Settings {
+    fn from(_: Dep) -> Self {
+        Settings("dep".to_string())
+    }
+}
+
+fn retries(_: &Ctx) -> u8 {
+    3
+}
+
+#[derive(FromContext)]
+#[context(Ctx)]
+struct Configured {
+    #[context(from = Dep)]
+    settings: Settings,
+    #[context(with = retries)]
+    retries: u8,
+    #[context(default)]
+    calls: std::sync::atomic::AtomicUsize,
+    #[context(default = 10)]
+    limit: usize,
+    #[context(skip)]
+    marker: std::marker::PhantomData<Dep>,
+}
+
+trait Repo {}
+
+impl Repo for Dep {}
+
+#[derive(FromContext)]
+#[context(C: Context)]
+struct Service<R: Repo, const N: usize>
+where
+    R: Send,
+{
+    repo: R,
+    #[context(skip)]
+    buffer: std::marker::PhantomData<[u8; N]>,
+}
+
+impl FromContext<Ctx> for &'static str {
+    fn from_context(_: &Ctx) -> Self {
+        "static"
+    }
+}
+
+#[derive(FromContext)]
+#[context(Ctx)]
+struct Borrowed<'a> {
+    name: &'a str,
+}
+
 struct ArgdService1 {
     name: String,
     deps: Deps,
@@ -64,6 +132,23 @@ impl CreateFromContext<Ctx> for ArgdService3 {
     }
 }
 
+#[derive(CreateFromContext)]
+#[context(C: Context)]
+struct Derived<T>
+where
+    T: Send,
+{
+    #[arg]
+    name: String,
+    #[arg]
+    value: T,
+    unit: (),
+}
+
+#[derive(CreateFromContext)]
+#[context(Ctx)]
+struct DerivedSingle(#[arg] u32, Deps);
+
 fn main() {
     let ctx = Ctx;
     let service1: ArgdService1 = ctx.create("name".to_string());
@@ -72,4 +157,22 @@ fn main() {
 
     assert_eq!(service1.name, "name");
     assert_eq!(service2.nval, 42);
+
+    let configured: Configured = ctx.provide();
+    assert_eq!(configured.settings.0, "dep");
+    assert_eq!(configured.retries, 3);
+    assert_eq!(configured.limit, 10);
+
+    let service: Service<Dep, 16> = ctx.provide();
+    let borrowed: Borrowed = ctx.provide();
+    assert_eq!(borrowed.name, "static");
+
+    let shared: Shared = ctx.provide();
+    let other: Shared = OtherCtx.provide();
+    assert_eq!(shared.version, other.version);
+
+    let derived: Derived<u8> = OtherCtx.create(("derived".to_string(), 7));
+    assert_eq!((derived.name.as_str(), derived.value), ("derived", 7));
+    let single: DerivedSingle = ctx.create(42);
+    assert_eq!(single.0, 42);
 }
@@ -2,43 +2,31 @@
 #![allow(unused)]
 
 use cream::{
-    context::{
-        events_context::EventsContext, ContextExtend, ContextProvide, CreamContext, FromContext,
-    },
+    context::{events_context::EventsContext, Context, CreamContext, FromContext},
     event_bus::EventBusPort,
-    pub_provide,
+    tasks::Tasks,
 };
 
-#[derive(FromContext)]
-#[context(MyCtx)]
+#[derive(Clone)]
 struct Dep1;
 
+#[derive(Context)]
 struct MyCtx {
+    #[extend]
+    cream: CreamContext,
+    #[extend]
     events: EventsContext,
+    #[provide]
     dep1: Dep1,
 }
 
-impl ContextExtend<EventsContext> for MyCtx {
-    fn provide_ctx(&self) -> &EventsContext {
-        &self.events
-    }
-}
-
-mod providings {
-    use cream::{context::events_context::EventsContext, event_bus::EventBusPort, pub_provide};
-
-    use crate::MyCtx;
-
-    pub_provide!(MyCtx : EventsContext {
-        EventBusPort
-    });
-}
-
 #[derive(FromContext)]
 #[context(MyCtx)]
 struct Service1 {
     dep1: Dep1,
+    // Exported by the extended contexts, no need to list them
     bus: EventBusPort,
+    tasks: Tasks,
 }
 
 fn main() {}
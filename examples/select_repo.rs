@@ -0,0 +1,67 @@
+use cream::context::{Context, FromContext};
+
+#[derive(Clone, Copy, Debug, PartialEq)]
+enum Backend {
+    Memory,
+    Postgres,
+}
+
+struct Ctx {
+    backend: Backend,
+}
+
+impl Context for Ctx {}
+
+impl FromContext<Ctx> for Backend {
+    fn from_context(ctx: &Ctx) -> Self {
+        ctx.backend
+    }
+}
+
+#[derive(FromContext)]
+#[context(Ctx)]
+struct MemoryRepo;
+
+#[derive(FromContext)]
+#[context(Ctx)]
+struct PostgresRepo;
+
+// Picked by an expression on the context
+#[derive(FromContext)]
+#[context(Ctx)]
+#[context(select = ctx.backend)]
+enum Repo {
+    #[context(when = Backend::Memory)]
+    Memory(MemoryRepo),
+    #[context(when = Backend::Postgres)]
+    Postgres(PostgresRepo),
+}
+
+// Picked by a value provided by the context, falling back to memory
+#[derive(FromContext)]
+#[context(Ctx)]
+#[context(selector = Backend)]
+enum Cache {
+    #[context(when = Backend::Postgres)]
+    Shared {
+        repo: PostgresRepo,
+    },
+    Local,
+}
+
+fn main() {
+    let ctx = Ctx {
+        backend: Backend::Postgres,
+    };
+    assert!(matches!(ctx.provide(), Repo::Postgres(PostgresRepo)));
+    assert!(matches!(
+        ctx.provide(),
+        Cache::Shared { repo: PostgresRepo }
+    ));
+
+    let ctx = Ctx {
+        backend: Backend::Memory,
+    };
+    assert!(matches!(ctx.provide(), Repo::Memory(MemoryRepo)));
+    assert!(matches!(ctx.provide(), Cache::Local));
+}
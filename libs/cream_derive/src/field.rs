@@ -0,0 +1,176 @@
+use proc_macro2::{Span, TokenStream, TokenTree};
+use quote::quote;
+use syn::spanned::Spanned;
+
+use crate::error::{span_compile_error, CompileError};
+
+/// Where a field's value comes from, set with `#[context(...)]` on the field
+#[derive(Debug)]
+pub(crate) enum FieldSource {
+    /// Resolved from the context, the default
+    Context,
+    /// `#[context(default)]` or `#[context(default = <expr>)]`
+    Default(Option<syn::Expr>),
+    /// `#[context(skip)]`, left out of the dependencies and filled with `Default::default()`
+    Skip,
+    /// `#[context(with = <path>)]`, a function called with the context
+    With(syn::Path),
+    /// `#[context(from = <Type>)]`, resolved as another type and converted with `Into`
+    From(syn::Type),
+}
+
+impl FieldSource {
+    pub fn parse_all(fields: &syn::Fields) -> Result<Vec<Self>, CompileError> {
+        fields.iter().map(Self::parse).collect()
+    }
+
+    pub fn parse(field: &syn::Field) -> Result<Self, CompileError> {
+        let mut source = None;
+        for attr in field
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("context"))
+        {
+            let span = attr.span();
+            if source.is_some() {
+                return Err(
+                    span_compile_error!(span => "a field takes a single #[context(...)] attribute"),
+                );
+            }
+
+            let syn::Meta::List(list) = &attr.meta else {
+                return Err(
+                    span_compile_error!(span => "expected #[context(default|skip|with = <path>|from = <Type>)]"),
+                );
+            };
+            source = Some(Self::parse_option(list.tokens.clone(), span)?);
+        }
+
+        Ok(source.unwrap_or(Self::Context))
+    }
+
+    fn parse_option(tokens: TokenStream, span: Span) -> Result<Self, CompileError> {
+        let mut tokens = tokens.into_iter();
+        let key = match tokens.next() {
+            Some(TokenTree::Ident(key)) => key,
+            Some(other) => {
+                return Err(
+                    span_compile_error!(other.span() => "expected one of `default`, `skip`, `with` or `from`"),
+                )
+            }
+            None => {
+                return Err(
+                    span_compile_error!(span => "expected one of `default`, `skip`, `with` or `from`"),
+                )
+            }
+        };
+
+        let value = match tokens.next() {
+            None => None,
+            Some(TokenTree::Punct(eq)) if eq.as_char() == '=' => {
+                let value: TokenStream = tokens.collect();
+                if value.is_empty() {
+                    return Err(span_compile_error!(eq.span() => "expected a value after `=`"));
+                }
+                Some(value)
+            }
+            Some(other) => return Err(span_compile_error!(other.span() => "expected `=`")),
+        };
+
+        let parse_error = |err: syn::Error| CompileError(err.to_compile_error());
+        match (key.to_string().as_str(), value) {
+            ("default", None) => Ok(Self::Default(None)),
+            ("default", Some(value)) => syn::parse2(value)
+                .map(|expr| Self::Default(Some(expr)))
+                .map_err(parse_error),
+            ("skip", None) => Ok(Self::Skip),
+            ("skip", Some(value)) => {
+                Err(span_compile_error!(value.span() => "`skip` does not take a value"))
+            }
+            ("with", Some(value)) => syn::parse2(value).map(Self::With).map_err(parse_error),
+            ("from", Some(value)) => syn::parse2(value).map(Self::From).map_err(parse_error),
+            ("with", None) => {
+                Err(span_compile_error!(key.span() => "expected #[context(with = <path>)]"))
+            }
+            ("from", None) => {
+                Err(span_compile_error!(key.span() => "expected #[context(from = <Type>)]"))
+            }
+            _ => Err(
+                span_compile_error!(key.span() => "expected one of `default`, `skip`, `with` or `from`"),
+            ),
+        }
+    }
+
+    /// The type resolved from the context for a field of type `ty`, if any
+    pub fn dependency<'a>(&'a self, ty: &'a syn::Type) -> Option<&'a syn::Type> {
+        match self {
+            Self::Context => Some(ty),
+            Self::From(from) => Some(from),
+            Self::Default(_) | Self::Skip | Self::With(_) => None,
+        }
+    }
+
+    /// The value of a field not resolved from the context, `None` for those which are
+    pub fn value(&self, ctx: TokenStream) -> Option<TokenStream> {
+        match self {
+            Self::Default(None) | Self::Skip => Some(quote! { ::std::default::Default::default() }),
+            Self::Default(Some(expr)) => Some(quote! { #expr }),
+            Self::With(path) => Some(quote! { #path(#ctx) }),
+            Self::Context | Self::From(_) => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    fn parse(field: syn::Field) -> Result<FieldSource, String> {
+        FieldSource::parse(&field).map_err(|err| err.0.to_string())
+    }
+
+    #[test]
+    fn parses_sources() {
+        assert!(matches!(
+            parse(parse_quote!(bar: String)),
+            Ok(FieldSource::Context)
+        ));
+        assert!(matches!(
+            parse(parse_quote!(#[context(default)] bar: usize)),
+            Ok(FieldSource::Default(None))
+        ));
+        assert!(matches!(
+            parse(parse_quote!(#[context(default = 42)] bar: usize)),
+            Ok(FieldSource::Default(Some(_)))
+        ));
+        assert!(matches!(
+            parse(parse_quote!(#[context(skip)] bar: PhantomData<T>)),
+            Ok(FieldSource::Skip)
+        ));
+        assert!(matches!(
+            parse(parse_quote!(#[context(with = crate::make_bar)] bar: Bar)),
+            Ok(FieldSource::With(_))
+        ));
+        assert!(matches!(
+            parse(parse_quote!(#[context(from = Config)] bar: Settings)),
+            Ok(FieldSource::From(_))
+        ));
+    }
+
+    #[test]
+    fn detects_misuse() {
+        let err = parse(parse_quote!(#[context(skip = true)] bar: usize)).unwrap_err();
+        assert!(err.contains("`skip` does not take a value"));
+
+        let err = parse(parse_quote!(#[context(with)] bar: usize)).unwrap_err();
+        assert!(err.contains("expected #[context(with = <path>)]"));
+
+        let err = parse(parse_quote!(#[context(fallback)] bar: usize)).unwrap_err();
+        assert!(err.contains("expected one of"));
+
+        let err = parse(parse_quote!(#[context(skip)] #[context(default)] bar: usize)).unwrap_err();
+        assert!(err.contains("a field takes a single"));
+    }
+}
@@ -0,0 +1,151 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+
+use crate::{
+    field::FieldSource,
+    gen_from_context::{
+        field_dependencies, gen_build, gen_test_impl, is_generic, overridable, ContextImpl,
+        ContextInput,
+    },
+};
+
+pub fn gen_async_from_context(input: syn::DeriveInput) -> TokenStream {
+    let ContextInput {
+        ident,
+        contexts,
+        generics,
+        fields,
+        sources,
+    } = match ContextInput::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.into(),
+    };
+
+    contexts
+        .iter()
+        .map(|context| {
+            let tys: Vec<_> = field_dependencies(&fields, &sources).collect();
+            // The future holds the context and the fields resolved so far across awaits
+            let ctx_name = context.name();
+            let mut predicates: Vec<syn::WherePredicate> = tys
+                .iter()
+                .filter(|ty| is_generic(ty, &generics))
+                .map(|ty| syn::parse_quote! { #ty: Send })
+                .collect();
+            if let ContextImpl::Generic { .. } = context {
+                predicates.push(syn::parse_quote! { #ctx_name: Sync });
+            }
+
+            let (ctx_name, header) = context.header_with(
+                quote! { AsyncFromContext },
+                quote! { AsyncFromContext },
+                &ident,
+                &generics,
+                &tys,
+                predicates,
+            );
+
+            let build_tokens = gen_build(quote! { Self }, &fields, &sources, |ty, source| {
+                if let Some(value) = source.value(quote! { ctx }) {
+                    return value;
+                }
+
+                let resolved_ty = match source {
+                    FieldSource::From(from) => from,
+                    _ => ty,
+                };
+                let resolved = if is_generic(resolved_ty, &generics) {
+                    quote! { <#resolved_ty as AsyncFromContext<#ctx_name>>::from_context_async(ctx).await }
+                } else {
+                    // Like the sync derives, services exported by sub-contexts are found too
+                    quote! {{
+                        use ::cream::context::{AsyncResolveDirect as _, AsyncResolveForwarded as _};
+                        (&::cream::context::AsyncResolver::<#resolved_ty, #ctx_name>::new())
+                            .resolve_async(ctx)
+                            .await
+                    }}
+                };
+                let resolved = match source {
+                    FieldSource::From(_) => quote! { ::std::convert::Into::into(#resolved) },
+                    _ => resolved,
+                };
+                overridable(ty, &generics, quote! { value }, resolved)
+            });
+
+            let test_impl = gen_test_impl(
+                context,
+                quote! { AsyncFromContext },
+                &ident,
+                &generics,
+                quote! {
+                    fn from_context_async(
+                        ctx: &::cream::context::TestContext<#ctx_name>,
+                    ) -> impl ::std::future::Future<Output = Self> + Send {
+                        ctx.resolve_async(<Self as AsyncFromContext<#ctx_name>>::from_context_async)
+                    }
+                },
+            );
+
+            quote! {
+                #header {
+                    fn from_context_async(ctx: &#ctx_name) -> impl ::std::future::Future<Output = Self> + Send {
+                        let fut = async move { #build_tokens };
+                        #[cfg(debug_assertions)]
+                        let fut = ::cream::context::ResolveGuard::enter_async::<Self, _>(fut);
+                        fut
+                    }
+                }
+
+                #test_impl
+            }
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn creates_generic_impl() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(C: MyContext)]
+            struct Foo {
+                bar: String,
+            }
+        );
+
+        let result = quote! {
+            impl <C: MyContext> AsyncFromContext<C> for Foo
+            where
+                C: Sync
+            {
+                fn from_context_async(ctx: &C) -> impl ::std::future::Future<Output = Self> + Send {
+                    let fut = async move {
+                        Self {
+                            bar: match ::cream::context::overridden::<String>() {
+                                Some(value) => value,
+                                None => {
+                                    use ::cream::context::{AsyncResolveDirect as _, AsyncResolveForwarded as _};
+                                    (&::cream::context::AsyncResolver::<String, C>::new())
+                                        .resolve_async(ctx)
+                                        .await
+                                },
+                            }
+                        }
+                    };
+                    #[cfg(debug_assertions)]
+                    let fut = ::cream::context::ResolveGuard::enter_async::<Self, _>(fut);
+                    fut
+                }
+            }
+        };
+
+        assert_eq!(
+            gen_async_from_context(input).to_string(),
+            result.to_string()
+        );
+    }
+}
@@ -0,0 +1,211 @@
+use proc_macro2::TokenStream;
+use quote::quote;
+use syn::spanned::Spanned;
+
+use crate::error::{span_compile_error, CompileError};
+
+pub fn gen_context(input: syn::DeriveInput) -> TokenStream {
+    let global_span = input.span();
+    let fields = match input.data {
+        syn::Data::Struct(data) => data.fields,
+        _ => return span_compile_error!(global_span => "only structs are supported").into(),
+    };
+
+    let ident = &input.ident;
+    let (impl_generics, ty_generics, where_clause) = input.generics.split_for_impl();
+    let ctx = quote! { #ident #ty_generics };
+
+    let mut impls = vec![quote! {
+        impl #impl_generics ::cream::context::Context for #ctx #where_clause {}
+    }];
+
+    for (i, field) in fields.iter().enumerate() {
+        let member = match &field.ident {
+            Some(name) => syn::Member::Named(name.clone()),
+            None => syn::Member::Unnamed(i.into()),
+        };
+        let ty = &field.ty;
+
+        let (extend, provide) = match FieldRole::parse(field) {
+            Ok(role) => (role.extend, role.provide),
+            Err(err) => return err.into(),
+        };
+
+        if extend {
+            impls.push(quote! {
+                impl #impl_generics ::cream::context::ContextExtend<#ty> for #ctx #where_clause {
+                    fn provide_ctx(&self) -> &#ty {
+                        &self.#member
+                    }
+                }
+            });
+        }
+
+        if provide {
+            // `impl FromContext<Ctx> for T` would cover every type
+            if is_bare_param(ty, &input.generics) {
+                return syn::Error::new_spanned(
+                    field,
+                    "#[provide] needs a concrete type, wrap the generic parameter in a local type",
+                )
+                .to_compile_error();
+            }
+
+            impls.push(quote! {
+                impl #impl_generics ::cream::context::FromContext<#ctx> for #ty #where_clause {
+                    fn from_context(ctx: &#ctx) -> Self {
+                        ::std::clone::Clone::clone(&ctx.#member)
+                    }
+                }
+            });
+        }
+    }
+
+    quote! { #(#impls)* }
+}
+
+/// Whether the type is a generic parameter, possibly behind a reference or a `Box`
+fn is_bare_param(ty: &syn::Type, generics: &syn::Generics) -> bool {
+    match ty {
+        syn::Type::Reference(reference) => is_bare_param(&reference.elem, generics),
+        syn::Type::Paren(paren) => is_bare_param(&paren.elem, generics),
+        syn::Type::Path(path) if path.qself.is_none() => {
+            let Some(segment) = path.path.segments.last() else {
+                return false;
+            };
+            match &segment.arguments {
+                syn::PathArguments::None if path.path.segments.len() == 1 => generics
+                    .type_params()
+                    .any(|param| param.ident == segment.ident),
+                syn::PathArguments::AngleBracketed(args) if segment.ident == "Box" => {
+                    match args.args.first() {
+                        Some(syn::GenericArgument::Type(inner)) => is_bare_param(inner, generics),
+                        _ => false,
+                    }
+                }
+                _ => false,
+            }
+        }
+        _ => false,
+    }
+}
+
+/// Whether a field is marked `#[extend]` and/or `#[provide]`
+struct FieldRole {
+    extend: bool,
+    provide: bool,
+}
+
+impl FieldRole {
+    fn parse(field: &syn::Field) -> Result<Self, CompileError> {
+        let mut role = Self {
+            extend: false,
+            provide: false,
+        };
+
+        for attr in &field.attrs {
+            let flag = if attr.path().is_ident("extend") {
+                &mut role.extend
+            } else if attr.path().is_ident("provide") {
+                &mut role.provide
+            } else {
+                continue;
+            };
+
+            if !matches!(attr.meta, syn::Meta::Path(_)) {
+                return Err(
+                    span_compile_error!(attr.span() => "expected a bare #[extend] or #[provide]"),
+                );
+            }
+            if *flag {
+                return Err(span_compile_error!(attr.span() => "duplicate attribute"));
+            }
+            *flag = true;
+        }
+
+        Ok(role)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn creates_context_impls() {
+        let input: syn::DeriveInput = parse_quote!(
+            struct AppContext {
+                #[extend]
+                events: EventsContext,
+                #[provide]
+                config: Config,
+                name: String,
+            }
+        );
+
+        let result = quote! {
+            impl ::cream::context::Context for AppContext {}
+
+            impl ::cream::context::ContextExtend<EventsContext> for AppContext {
+                fn provide_ctx(&self) -> &EventsContext {
+                    &self.events
+                }
+            }
+
+            impl ::cream::context::FromContext<AppContext> for Config {
+                fn from_context(ctx: &AppContext) -> Self {
+                    ::std::clone::Clone::clone(&ctx.config)
+                }
+            }
+        };
+
+        assert_eq!(gen_context(input).to_string(), result.to_string());
+    }
+
+    #[test]
+    fn supports_tuple_structs() {
+        let input: syn::DeriveInput = parse_quote!(
+            struct AppContext<T: Clone>(#[extend] CreamContext, #[provide] Shared<T>);
+        );
+
+        let output = gen_context(input).to_string();
+        assert!(output.contains(&quote! { &self.0 }.to_string()));
+        assert!(output.contains(&quote! { ::std::clone::Clone::clone(&ctx.1) }.to_string()));
+        assert!(output.contains(&quote! { for Shared<T> }.to_string()));
+    }
+
+    #[test]
+    fn rejects_generic_parameters() {
+        for input in [
+            parse_quote!(
+                struct AppContext<T: Clone>(#[provide] T);
+            ),
+            parse_quote!(
+                struct AppContext<'a, T>(#[provide] &'a T);
+            ),
+            parse_quote!(
+                struct AppContext<T: Clone>(#[provide] Box<T>);
+            ),
+        ] {
+            assert!(gen_context(input)
+                .to_string()
+                .contains("#[provide] needs a concrete type"));
+        }
+    }
+
+    #[test]
+    fn detects_invalid_attributes() {
+        let input: syn::DeriveInput = parse_quote!(
+            struct AppContext {
+                #[extend(all)]
+                events: EventsContext,
+            }
+        );
+
+        assert!(gen_context(input)
+            .to_string()
+            .contains("expected a bare #[extend] or #[provide]"));
+    }
+}
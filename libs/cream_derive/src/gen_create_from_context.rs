@@ -0,0 +1,211 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+use syn::spanned::Spanned;
+
+use crate::{
+    error::{span_compile_error, CompileError},
+    field::FieldSource,
+    gen_from_context::{from_context_mapping, ContextInput},
+};
+
+pub fn gen_create_from_context(input: syn::DeriveInput) -> TokenStream {
+    let ContextInput {
+        ident,
+        contexts,
+        generics,
+        fields,
+        sources,
+    } = match ContextInput::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.into(),
+    };
+
+    // The variable of every `#[arg]` field, in declaration order
+    let mut args = Vec::new();
+    for (i, (field, source)) in fields.iter().zip(&sources).enumerate() {
+        let Some(attr) = field.attrs.iter().find(|attr| attr.path().is_ident("arg")) else {
+            args.push(None);
+            continue;
+        };
+
+        if let Err(err) = check_arg(attr, source, field) {
+            return err.into();
+        }
+
+        let var = match &field.ident {
+            Some(name) => name.clone(),
+            None => Ident::new(&format!("arg_{}", i), Span::call_site()),
+        };
+        args.push(Some((var, &field.ty)));
+    }
+
+    let arg_vars: Vec<_> = args.iter().flatten().map(|(var, _)| var).collect();
+    let arg_tys: Vec<_> = args.iter().flatten().map(|(_, ty)| ty).collect();
+    let (args_ty, args_pat) = match (arg_vars.as_slice(), arg_tys.as_slice()) {
+        ([var], [ty]) => (quote! { #ty }, quote! { #var }),
+        _ => (quote! { (#(#arg_tys,)*) }, quote! { (#(#arg_vars,)*) }),
+    };
+
+    // Arguments are not resolved from the context
+    let resolved: Vec<_> = fields
+        .iter()
+        .zip(&sources)
+        .zip(&args)
+        .filter(|(_, arg)| arg.is_none())
+        .map(|(field_source, _)| field_source)
+        .collect();
+    let resolved_fields: Vec<_> = resolved.iter().map(|(field, _)| field).collect();
+    let resolved_sources: Vec<_> = resolved.iter().map(|(_, source)| source).collect();
+
+    contexts
+        .iter()
+        .map(|context| {
+            let tys: Vec<_> = resolved_fields
+                .iter()
+                .zip(&resolved_sources)
+                .filter_map(|(field, source)| source.dependency(&field.ty))
+                .collect();
+
+            let (ctx_name, header) = context.header_with(
+                quote! { CreateFromContext },
+                quote! { FromContext },
+                &ident,
+                &generics,
+                &tys,
+                Vec::new(),
+            );
+
+            let mappings = fields
+                .iter()
+                .zip(&sources)
+                .zip(&args)
+                .map(|((field, source), arg)| {
+                    let mapping = match arg {
+                        Some((var, _)) => quote! { #var },
+                        None => from_context_mapping(&field.ty, source, &ctx_name, &generics),
+                    };
+                    match &field.ident {
+                        Some(name) => quote! { #name: #mapping },
+                        None => mapping,
+                    }
+                });
+            let build_tokens = match fields {
+                syn::Fields::Named(_) => quote! { Self { #(#mappings),* } },
+                syn::Fields::Unnamed(_) => quote! { Self ( #(#mappings),* ) },
+                syn::Fields::Unit => quote! { Self },
+            };
+
+            quote! {
+                #header {
+                    type Args = #args_ty;
+
+                    fn create_from_context(ctx: &#ctx_name, args: Self::Args) -> Self {
+                        #[cfg(debug_assertions)]
+                        let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                        let #args_pat = args;
+                        #build_tokens
+                    }
+                }
+            }
+        })
+        .collect()
+}
+
+/// An `#[arg]` is a bare marker on a field which is not otherwise sourced
+fn check_arg(
+    attr: &syn::Attribute,
+    source: &FieldSource,
+    field: &syn::Field,
+) -> Result<(), CompileError> {
+    if !matches!(attr.meta, syn::Meta::Path(_)) {
+        return Err(span_compile_error!(attr.span() => "expected a bare #[arg]"));
+    }
+
+    if !matches!(source, FieldSource::Context) {
+        let span = field
+            .attrs
+            .iter()
+            .find(|attr| attr.path().is_ident("context"))
+            .map_or_else(|| field.span(), |attr| attr.span());
+        return Err(span_compile_error!(span => "an #[arg] field can not take #[context(...)]"));
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn creates_impl_with_args() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(C: MyContext)]
+            struct Foo {
+                #[arg]
+                name: String,
+                dep: Dep,
+                #[arg]
+                retries: u8,
+            }
+        );
+
+        let result = quote! {
+            impl <C: MyContext> CreateFromContext<C> for Foo {
+                type Args = (String, u8,);
+
+                fn create_from_context(ctx: &C, args: Self::Args) -> Self {
+                    #[cfg(debug_assertions)]
+                    let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                    let (name, retries,) = args;
+                    Self {
+                        name: name,
+                        dep: match ::cream::context::overridden::<Dep>() {
+                            Some(value) => value,
+                            None => {
+                                use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                                (&::cream::context::Resolver::<Dep, C>::new()).resolve(ctx)
+                            },
+                        },
+                        retries: retries
+                    }
+                }
+            }
+        };
+
+        assert_eq!(
+            gen_create_from_context(input).to_string(),
+            result.to_string()
+        );
+    }
+
+    #[test]
+    fn uses_single_arg_as_args() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            struct Foo(#[arg] u32, Dep);
+        );
+
+        let output = gen_create_from_context(input).to_string();
+        assert!(output.contains(&quote! { type Args = u32; }.to_string()));
+        assert!(output.contains(&quote! { let arg_0 = args; }.to_string()));
+    }
+
+    #[test]
+    fn detects_sourced_args() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            struct Foo {
+                #[arg]
+                #[context(default)]
+                name: String,
+            }
+        );
+
+        assert!(gen_create_from_context(input)
+            .to_string()
+            .contains("an #[arg] field can not take #[context(...)]"));
+    }
+}
@@ -1,85 +1,651 @@
-use proc_macro2::{Ident, Span, TokenStream};
-use quote::quote;
-use syn::spanned::Spanned;
+use proc_macro2::{Ident, Span, TokenStream, TokenTree};
+use quote::{quote, ToTokens};
+use syn::{spanned::Spanned, visit::Visit};
 
 use crate::{
     common::streams_equal,
     error::{span_compile_error, CompileError},
+    field::FieldSource,
+    select::{SelectVariant, Selection},
 };
 
 pub fn gen_from_context(input: syn::DeriveInput) -> TokenStream {
-    let global_span = input.span();
-    let ast = match input.data {
-        syn::Data::Struct(data) => data,
-        _ => return span_compile_error!(global_span => "only structs are supported").into(),
+    if let syn::Data::Enum(_) = input.data {
+        return gen_enum_from_context(input);
+    }
+
+    let ContextInput {
+        ident,
+        contexts,
+        generics,
+        fields,
+        sources,
+    } = match ContextInput::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.into(),
     };
 
-    let Some(attr) = input.attrs.into_iter().next() else {
-        return span_compile_error!(global_span => "Missing #[context] attribute").into();
+    contexts
+        .iter()
+        .map(|context| {
+            let tys: Vec<_> = field_dependencies(&fields, &sources).collect();
+            let (ctx_name, header) =
+                context.header(quote! { FromContext }, &ident, &generics, &tys);
+
+            let dependencies = gen_dependencies(tys.iter().copied(), |ty| {
+                add_dependencies(ty, &ctx_name, &generics)
+            });
+
+            let build_tokens = gen_build(quote! { Self }, &fields, &sources, |ty, source| {
+                from_context_mapping(ty, source, &ctx_name, &generics)
+            });
+
+            let test_impl = gen_test_impl(
+                context,
+                quote! { FromContext },
+                &ident,
+                &generics,
+                quote! {
+                    fn from_context(ctx: &::cream::context::TestContext<#ctx_name>) -> Self {
+                        ctx.resolve_with(<Self as FromContext<#ctx_name>>::from_context)
+                    }
+
+                    fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                        <Self as FromContext<#ctx_name>>::dependencies(graph)
+                    }
+                },
+            );
+
+            quote! {
+                #header {
+                    fn from_context(ctx: &#ctx_name) -> Self {
+                        #[cfg(debug_assertions)]
+                        let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                        #build_tokens
+                    }
+
+                    #dependencies
+                }
+
+                #test_impl
+            }
+        })
+        .collect()
+}
+
+fn gen_enum_from_context(input: syn::DeriveInput) -> TokenStream {
+    let EnumInput {
+        ident,
+        contexts,
+        generics,
+        selection,
+        variants,
+    } = match EnumInput::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.into(),
     };
 
-    let FromContextAttr { context } = match FromContextAttr::parse(attr) {
-        Ok(attr) => attr,
-        Err(err) => return CompileError::from(err).into(),
+    contexts
+        .iter()
+        .map(|context| {
+            let selector_ty = match &selection {
+                Selection::Type(ty) => Some(ty),
+                Selection::Expr(_) => None,
+            };
+            let tys: Vec<_> = selector_ty
+                .into_iter()
+                .chain(
+                    variants
+                        .iter()
+                        .flat_map(|variant| field_dependencies(&variant.fields, &variant.sources)),
+                )
+                .collect();
+            let (ctx_name, header) =
+                context.header(quote! { FromContext }, &ident, &generics, &tys);
+
+            let dependencies = gen_dependencies(tys.iter().copied(), |ty| {
+                add_dependencies(ty, &ctx_name, &generics)
+            });
+
+            let selected = match &selection {
+                Selection::Expr(expr) => quote! { #expr },
+                Selection::Type(ty) => {
+                    from_context_mapping(ty, &FieldSource::Context, &ctx_name, &generics)
+                }
+            };
+            let arms = gen_arms(&variants, |ty, source| {
+                from_context_mapping(ty, source, &ctx_name, &generics)
+            });
+
+            let test_impl = gen_test_impl(
+                context,
+                quote! { FromContext },
+                &ident,
+                &generics,
+                quote! {
+                    fn from_context(ctx: &::cream::context::TestContext<#ctx_name>) -> Self {
+                        ctx.resolve_with(<Self as FromContext<#ctx_name>>::from_context)
+                    }
+
+                    fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                        <Self as FromContext<#ctx_name>>::dependencies(graph)
+                    }
+                },
+            );
+
+            quote! {
+                #header {
+                    fn from_context(ctx: &#ctx_name) -> Self {
+                        #[cfg(debug_assertions)]
+                        let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                        match #selected {
+                            #(#arms),*
+                        }
+                    }
+
+                    #dependencies
+                }
+
+                #test_impl
+            }
+        })
+        .collect()
+}
+
+/// The match arms building every variant, the fallback, if any, going last
+fn gen_arms(
+    variants: &[SelectVariant],
+    mapping: impl Fn(&syn::Type, &FieldSource) -> TokenStream,
+) -> Vec<TokenStream> {
+    variants
+        .iter()
+        .filter(|variant| variant.when.is_some())
+        .chain(variants.iter().filter(|variant| variant.when.is_none()))
+        .map(|variant| {
+            let name = &variant.ident;
+            let build = gen_build(
+                quote! { Self::#name },
+                &variant.fields,
+                &variant.sources,
+                &mapping,
+            );
+            match &variant.when {
+                Some(pat) => quote! { #pat => #build },
+                None => quote! { _ => #build },
+            }
+        })
+        .collect()
+}
+
+/// Whether the derives look up the overrides of a `TestContext`, with the `test-context` feature
+pub(crate) const TEST_CONTEXT: bool = cfg!(feature = "test-context");
+
+/// Impl of `trait_name` for the `TestContext` of a static `context`, with `items`, emitted with
+/// the `test-context` feature
+///
+/// The items resolve from the wrapped context with the overrides in effect, which the fields
+/// then take, see [`overridable`]
+pub(crate) fn gen_test_impl(
+    context: &ContextImpl,
+    trait_name: TokenStream,
+    ident: &Ident,
+    generics: &syn::Generics,
+    items: TokenStream,
+) -> TokenStream {
+    let Some(test_context) = test_context(context).filter(|_| TEST_CONTEXT) else {
+        return TokenStream::new();
     };
 
-    let struct_name = &input.ident;
+    let ctx_name = context.name();
+    let mut predicates: Vec<syn::WherePredicate> = Vec::new();
+    if !generics.params.is_empty() {
+        let (_, ty_generics, _) = generics.split_for_impl();
+        predicates.push(syn::parse_quote! { #ident #ty_generics: #trait_name<#ctx_name> });
+    }
+    let (_, header) = test_context.header_with(
+        trait_name.clone(),
+        trait_name.clone(),
+        ident,
+        generics,
+        &[],
+        predicates,
+    );
 
-    let (ctx_name, header) = match context {
-        ContextImpl::Static(context_name) => {
-            let tokens = quote! { impl FromContext<#context_name> for #struct_name };
-            (context_name, tokens)
+    quote! {
+        #header {
+            #items
         }
+    }
+}
 
-        ContextImpl::Generic { ident, bounds } => {
-            let tokens = quote! { impl <#ident: #bounds> FromContext<#ident> for #struct_name };
-            (ident, tokens)
+/// Builds `path` with every field mapped from its type and source
+pub(crate) fn gen_build(
+    path: TokenStream,
+    fields: &syn::Fields,
+    sources: &[FieldSource],
+    mapping: impl Fn(&syn::Type, &FieldSource) -> TokenStream,
+) -> TokenStream {
+    let mappings = fields.iter().zip(sources).map(|(field, source)| {
+        let mapping = mapping(&field.ty, source);
+        match &field.ident {
+            Some(name) => quote! { #name: #mapping },
+            None => mapping,
         }
-    };
+    });
 
-    let build_tokens = match ast.fields {
-        syn::Fields::Named(fields) => {
-            let mappings = fields.named.iter().map(|field| {
-                let ty = &field.ty;
-                let mapping = quote! { <#ty as FromContext<#ctx_name>>::from_context(ctx) };
-                let name = field.ident.as_ref().expect("expected named field");
+    match fields {
+        syn::Fields::Named(_) => quote! { #path { #(#mappings),* } },
+        syn::Fields::Unnamed(_) => quote! { #path ( #(#mappings),* ) },
+        syn::Fields::Unit => quote! { #path },
+    }
+}
 
-                quote! { #name: #mapping }
-            });
+pub(crate) fn from_context_mapping(
+    ty: &syn::Type,
+    source: &FieldSource,
+    ctx_name: &TokenStream,
+    generics: &syn::Generics,
+) -> TokenStream {
+    if let Some(value) = source.value(quote! { ctx }) {
+        return value;
+    }
 
-            quote! { Self { #(#mappings),* } }
+    let resolution = match source {
+        FieldSource::From(from) => {
+            let resolution = resolve(from, ctx_name, generics);
+            quote! { ::std::convert::Into::into(#resolution) }
         }
-        syn::Fields::Unnamed(fields) => {
-            let mappings = fields.unnamed.iter().map(|field| {
-                let ty = &field.ty;
-                let mapping = quote! { <#ty as FromContext<#ctx_name>>::from_context(ctx) };
-                quote! { #mapping }
-            });
+        _ => resolve(ty, ctx_name, generics),
+    };
+    overridable(ty, generics, quote! { value }, resolution)
+}
 
-            quote! { Self ( #(#mappings),* ) }
-        }
-        syn::Fields::Unit => {
-            quote! { Self }
+/// The `TestContext` of a static context, which the derives also implement their trait for
+/// with the `test-context` feature
+pub(crate) fn test_context(context: &ContextImpl) -> Option<ContextImpl> {
+    match context {
+        ContextImpl::Static(ty) => Some(ContextImpl::Static(
+            syn::parse_quote! { ::cream::context::TestContext<#ty> },
+        )),
+        ContextImpl::Generic { .. } => None,
+    }
+}
+
+/// Lets an override of `ty` in effect win over `resolution`, `found` uses its `value`
+///
+/// Only with the `test-context` feature. Overrides are looked up by type id, so types borrowing
+/// or using generics are always resolved
+pub(crate) fn overridable(
+    ty: &syn::Type,
+    generics: &syn::Generics,
+    found: TokenStream,
+    resolution: TokenStream,
+) -> TokenStream {
+    if !TEST_CONTEXT || borrows(ty.to_token_stream()) || is_generic(ty, generics) {
+        return resolution;
+    }
+
+    quote! {
+        match ::cream::context::overridden::<#ty>() {
+            Some(value) => #found,
+            None => #resolution,
         }
+    }
+}
+
+/// Builds `ty` from `ctx`
+///
+/// Goes through `Resolver` so services exported by sub-contexts are found too, a `FromContext`
+/// impl for the context winning, or through `ScopeResolver` for a `Scope`. Generic types are
+/// bound by `FromContext` in the where-clause instead, and only found through it
+pub(crate) fn resolve(
+    ty: &syn::Type,
+    ctx_name: &TokenStream,
+    generics: &syn::Generics,
+) -> TokenStream {
+    resolver_call(
+        ty,
+        ctx_name,
+        generics,
+        quote! { from_context(ctx) },
+        quote! { resolve(ctx) },
+    )
+}
+
+/// Adds the dependencies of `ty` to `graph`, found like in [`resolve`]
+pub(crate) fn add_dependencies(
+    ty: &syn::Type,
+    ctx_name: &TokenStream,
+    generics: &syn::Generics,
+) -> TokenStream {
+    resolver_call(
+        ty,
+        ctx_name,
+        generics,
+        quote! { dependencies(graph) },
+        quote! { dependencies(graph) },
+    )
+}
+
+fn resolver_call(
+    ty: &syn::Type,
+    ctx_name: &TokenStream,
+    generics: &syn::Generics,
+    generic_call: TokenStream,
+    method_call: TokenStream,
+) -> TokenStream {
+    if is_generic(ty, generics) {
+        return quote! { <#ty as FromContext<#ctx_name>>::#generic_call };
+    }
+
+    match scope_parent(ctx_name) {
+        Some(parent) => quote! {{
+            use ::cream::context::{ScopeResolveDirect as _, ScopeResolveFallback as _};
+            (&::cream::context::ScopeResolver::<#ty, #parent>::new()).#method_call
+        }},
+        None => quote! {{
+            use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+            (&::cream::context::Resolver::<#ty, #ctx_name>::new()).#method_call
+        }},
+    }
+}
+
+/// The parent of a `Scope<Parent>` context, whose services take their plain fields from the scope
+/// first, see `ScopeResolver`
+fn scope_parent(ctx_name: &TokenStream) -> Option<syn::Type> {
+    let syn::Type::Path(ty) = syn::parse2(ctx_name.clone()).ok()? else {
+        return None;
+    };
+    let segment = ty.path.segments.last()?;
+    let syn::PathArguments::AngleBracketed(args) = &segment.arguments else {
+        return None;
     };
 
+    match args.args.first() {
+        Some(syn::GenericArgument::Type(parent))
+            if segment.ident == "Scope" && args.args.len() == 1 =>
+        {
+            Some(parent.clone())
+        }
+        _ => None,
+    }
+}
+
+/// The types the fields are resolved as, skipping those which are not
+pub(crate) fn field_dependencies<'a>(
+    fields: &'a syn::Fields,
+    sources: &'a [FieldSource],
+) -> impl Iterator<Item = &'a syn::Type> {
+    fields
+        .iter()
+        .zip(sources)
+        .filter_map(|(field, source)| source.dependency(&field.ty))
+}
+
+/// Declares every type as a dependency of the service in the `DependencyGraph`
+///
+/// `dependencies` gives the call adding the dependencies of a type to `graph` in turn
+pub(crate) fn gen_dependencies<'a>(
+    tys: impl IntoIterator<Item = &'a syn::Type>,
+    dependencies: impl Fn(&syn::Type) -> TokenStream,
+) -> TokenStream {
+    let tys: Vec<_> = tys.into_iter().collect();
+    let dependencies = tys.iter().map(|ty| dependencies(ty));
+
     quote! {
-        #header {
-            fn from_context(ctx: &#ctx_name) -> Self {
-                #build_tokens
+        fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+            if graph.visit::<Self>() {
+                #(
+                    graph.depends::<Self, #tys>();
+                    #dependencies;
+                )*
             }
         }
     }
 }
 
+/// A struct deriving one of the context traits, with its `#[context]` attribute parsed
+pub(crate) struct ContextInput {
+    pub ident: Ident,
+    pub contexts: Vec<ContextImpl>,
+    pub generics: syn::Generics,
+    pub fields: syn::Fields,
+    pub sources: Vec<FieldSource>,
+}
+
+impl ContextInput {
+    pub fn parse(input: syn::DeriveInput) -> Result<Self, CompileError> {
+        let global_span = input.span();
+        let ast = match input.data {
+            syn::Data::Struct(data) => data,
+            _ => return Err(span_compile_error!(global_span => "only structs are supported")),
+        };
+
+        Ok(Self {
+            contexts: parse_contexts(&input.attrs, global_span)?,
+            ident: input.ident,
+            generics: input.generics,
+            sources: FieldSource::parse_all(&ast.fields)?,
+            fields: ast.fields,
+        })
+    }
+}
+
+/// An enum deriving `FromContext`, with the selection picking its variant
+pub(crate) struct EnumInput {
+    pub ident: Ident,
+    pub contexts: Vec<ContextImpl>,
+    pub generics: syn::Generics,
+    pub selection: Selection,
+    pub variants: Vec<SelectVariant>,
+}
+
+impl EnumInput {
+    pub fn parse(input: syn::DeriveInput) -> Result<Self, CompileError> {
+        let global_span = input.span();
+        let ast = match input.data {
+            syn::Data::Enum(data) => data,
+            _ => return Err(span_compile_error!(global_span => "expected an enum")),
+        };
+
+        let contexts = parse_contexts(&input.attrs, global_span)?;
+        let selection = input
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("context"))
+            .find_map(Selection::parse)
+            .unwrap_or_else(|| {
+                Err(span_compile_error!(global_span => "expected #[context(select = <expr>)] or #[context(selector = <Type>)]"))
+            })?;
+
+        if ast.variants.is_empty() {
+            return Err(span_compile_error!(global_span => "expected at least one variant"));
+        }
+
+        let mut variants = Vec::with_capacity(ast.variants.len());
+        let mut fallback = false;
+        for variant in ast.variants {
+            let span = variant.span();
+            let variant = SelectVariant::parse(variant)?;
+            if variant.when.is_none() {
+                if fallback {
+                    return Err(
+                        span_compile_error!(span => "only one variant can omit #[context(when = <pattern>)]"),
+                    );
+                }
+                fallback = true;
+            }
+            variants.push(variant);
+        }
+
+        Ok(Self {
+            ident: input.ident,
+            contexts,
+            generics: input.generics,
+            selection,
+            variants,
+        })
+    }
+}
+
+/// Every context named by the `#[context(...)]` attributes, skipping unrelated ones
+fn parse_contexts(attrs: &[syn::Attribute], span: Span) -> Result<Vec<ContextImpl>, CompileError> {
+    let mut contexts = Vec::new();
+    for attr in attrs
+        .iter()
+        .filter(|attr| attr.path().is_ident("context"))
+        .filter(|attr| Selection::parse(attr).is_none())
+    {
+        let FromContextAttr { contexts: parsed } = FromContextAttr::parse(attr.clone())?;
+        contexts.extend(parsed);
+    }
+
+    if contexts.is_empty() {
+        return Err(span_compile_error!(span => "Missing #[context] attribute"));
+    }
+    Ok(contexts)
+}
+
+impl ContextImpl {
+    /// The context type, or the ident of the generic context
+    pub fn name(&self) -> TokenStream {
+        match self {
+            ContextImpl::Static(context_name) => quote! { #context_name },
+            ContextImpl::Generic { ident, .. } => quote! { #ident },
+        }
+    }
+
+    /// The context type and the `impl <trait><context> for <ident>` header
+    ///
+    /// Dependencies using the generic parameters get a `Dep: <trait><context>` bound
+    pub fn header(
+        &self,
+        trait_name: TokenStream,
+        ident: &Ident,
+        generics: &syn::Generics,
+        dependencies: &[&syn::Type],
+    ) -> (TokenStream, TokenStream) {
+        self.header_with(
+            trait_name.clone(),
+            trait_name,
+            ident,
+            generics,
+            dependencies,
+            Vec::new(),
+        )
+    }
+
+    /// Like `header`, with the dependencies bound by `dependency_trait` instead and `predicates`
+    /// added to the where clause
+    pub fn header_with(
+        &self,
+        trait_name: TokenStream,
+        dependency_trait: TokenStream,
+        ident: &Ident,
+        generics: &syn::Generics,
+        dependencies: &[&syn::Type],
+        predicates: Vec<syn::WherePredicate>,
+    ) -> (TokenStream, TokenStream) {
+        let mut impl_generics = generics.clone();
+        let ctx_name = self.name();
+        match self {
+            ContextImpl::Static(_) => {}
+            ContextImpl::Generic { ident: ctx, bounds } => {
+                impl_generics
+                    .params
+                    .push(syn::parse_quote! { #ctx: #bounds });
+            }
+        }
+
+        let where_clause = impl_generics.make_where_clause();
+        for ty in dependencies.iter().filter(|ty| is_generic(ty, generics)) {
+            where_clause
+                .predicates
+                .push(syn::parse_quote! { #ty: #dependency_trait<#ctx_name> });
+        }
+        where_clause.predicates.extend(predicates);
+
+        let (impl_generics, _, where_clause) = impl_generics.split_for_impl();
+        let (_, ty_generics, _) = generics.split_for_impl();
+        let tokens = quote! {
+            impl #impl_generics #trait_name<#ctx_name> for #ident #ty_generics #where_clause
+        };
+        (ctx_name, tokens)
+    }
+}
+
+/// Whether the type uses any of the generic parameters
+pub(crate) fn is_generic(ty: &syn::Type, generics: &syn::Generics) -> bool {
+    let mut params = UsesParams {
+        generics,
+        found: false,
+    };
+    params.visit_type(ty);
+    params.found
+}
+
+/// Looks for the generic parameters in paths and lifetimes, not in any ident with their name
+struct UsesParams<'a> {
+    generics: &'a syn::Generics,
+    found: bool,
+}
+
+impl UsesParams<'_> {
+    fn is_param(&self, ident: &Ident) -> bool {
+        self.generics.params.iter().any(|param| match param {
+            syn::GenericParam::Type(param) => param.ident == *ident,
+            syn::GenericParam::Const(param) => param.ident == *ident,
+            syn::GenericParam::Lifetime(_) => false,
+        })
+    }
+}
+
+impl<'ast> Visit<'ast> for UsesParams<'_> {
+    fn visit_type_path(&mut self, ty: &'ast syn::TypePath) {
+        // `T::Assoc` starts with a parameter, `<T as Trait>::Assoc` goes through the qself
+        if ty.qself.is_none() && ty.path.leading_colon.is_none() {
+            let first = ty.path.segments.first();
+            self.found |= first.is_some_and(|segment| self.is_param(&segment.ident));
+        }
+        syn::visit::visit_type_path(self, ty);
+    }
+
+    fn visit_expr_path(&mut self, expr: &'ast syn::ExprPath) {
+        // Const parameters, e.g. `[u8; N]`
+        if expr.qself.is_none() {
+            self.found |= expr
+                .path
+                .get_ident()
+                .is_some_and(|ident| self.is_param(ident));
+        }
+        syn::visit::visit_expr_path(self, expr);
+    }
+
+    fn visit_lifetime(&mut self, lifetime: &'ast syn::Lifetime) {
+        self.found |= self
+            .generics
+            .lifetimes()
+            .any(|param| param.lifetime == *lifetime);
+    }
+}
+
+/// Whether the tokens hold a reference or a lifetime
+fn borrows(tokens: TokenStream) -> bool {
+    tokens.into_iter().any(|token| match token {
+        TokenTree::Punct(punct) => punct.as_char() == '\'' || punct.as_char() == '&',
+        TokenTree::Group(group) => borrows(group.stream()),
+        _ => false,
+    })
+}
+
 #[derive(Debug, PartialEq)]
 struct FromContextAttr {
-    context: ContextImpl,
+    contexts: Vec<ContextImpl>,
 }
 
 #[derive(Debug)]
-enum ContextImpl {
-    Static(Ident),
+pub(crate) enum ContextImpl {
+    Static(syn::Type),
     Generic { ident: Ident, bounds: TokenStream },
 }
 
@@ -145,8 +711,8 @@ impl From<Error> for CompileError {
 impl FromContextAttr {
     fn parse(attr: syn::Attribute) -> Result<Self, Error> {
         let span = attr.span();
-        let mut tokens = match attr.meta {
-            syn::Meta::List(list) => list.tokens.into_iter(),
+        let tokens = match attr.meta {
+            syn::Meta::List(list) => list.tokens,
             _ => {
                 return Err(Error {
                     kind: ErrorKind::InvalidMeta,
@@ -155,8 +721,25 @@ impl FromContextAttr {
             }
         };
 
+        if tokens.is_empty() {
+            return Err(Error {
+                kind: ErrorKind::NoContextIdent,
+                span,
+            });
+        }
+
+        let contexts = split_contexts(tokens)
+            .into_iter()
+            .map(|tokens| Self::parse_context(tokens, span))
+            .collect::<Result<_, _>>()?;
+
+        Ok(Self { contexts })
+    }
+
+    fn parse_context(tokens: TokenStream, span: Span) -> Result<ContextImpl, Error> {
+        let mut tokens = tokens.into_iter();
         let context_ident = match tokens.next() {
-            Some(proc_macro2::TokenTree::Ident(context)) => context,
+            Some(TokenTree::Ident(context)) => context,
             None => {
                 return Err(Error {
                     kind: ErrorKind::NoContextIdent,
@@ -171,35 +754,74 @@ impl FromContextAttr {
             }
         };
 
-        let has_colon = match tokens.next() {
-            Some(proc_macro2::TokenTree::Punct(colon)) if colon.as_char() == ':' => true,
-            None => false,
-            _ => {
-                return Err(Error {
-                    kind: ErrorKind::InvalidBounds,
-                    span,
-                })
-            }
-        };
+        let rest = tokens.collect::<TokenStream>();
+        let mut rest_tokens = rest.clone().into_iter();
 
-        let tokens = tokens.collect::<TokenStream>();
+        // A lone `:` starts the bounds, anything else is part of the context type, like `::` or `<`
+        match rest_tokens.next() {
+            Some(TokenTree::Punct(colon))
+                if colon.as_char() == ':' && colon.spacing() == proc_macro2::Spacing::Alone =>
+            {
+                let bounds = rest_tokens.collect::<TokenStream>();
+                if bounds.is_empty() {
+                    return Err(Error {
+                        kind: ErrorKind::InvalidBounds,
+                        span: bounds.span(),
+                    });
+                }
 
-        match (has_colon, tokens.is_empty()) {
-            (false, true) => Ok(Self {
-                context: ContextImpl::Static(context_ident),
-            }),
-            (true, false) => Ok(Self {
-                context: ContextImpl::Generic {
+                Ok(ContextImpl::Generic {
                     ident: context_ident,
-                    bounds: tokens,
-                },
-            }),
-            _ => Err(Error {
-                kind: ErrorKind::InvalidBounds,
-                span: tokens.span(),
-            }),
+                    bounds,
+                })
+            }
+            _ => {
+                let ty = quote! { #context_ident #rest };
+                match syn::parse2::<syn::Type>(ty) {
+                    Ok(ty) => Ok(ContextImpl::Static(ty)),
+                    Err(_) => Err(Error {
+                        kind: ErrorKind::InvalidContextIdent,
+                        span,
+                    }),
+                }
+            }
+        }
+    }
+}
+
+/// Splits `A, B<X, Y>, C: Bound` on the commas outside of generic arguments
+fn split_contexts(tokens: TokenStream) -> Vec<TokenStream> {
+    let mut contexts = vec![TokenStream::new()];
+    let mut depth = 0usize;
+    let mut arrow = false;
+    for token in tokens {
+        if let TokenTree::Punct(punct) = &token {
+            match punct.as_char() {
+                '<' => depth += 1,
+                // The `>` of a `->` does not close anything
+                '>' if !arrow => depth = depth.saturating_sub(1),
+                ',' if depth == 0 => {
+                    contexts.push(TokenStream::new());
+                    continue;
+                }
+                _ => {}
+            }
+            arrow = punct.as_char() == '-' && punct.spacing() == proc_macro2::Spacing::Joint;
+        } else {
+            arrow = false;
         }
+
+        contexts
+            .last_mut()
+            .expect("always at least one context")
+            .extend([token]);
+    }
+
+    // Allows a trailing comma
+    if contexts.len() > 1 && contexts.last().is_some_and(TokenStream::is_empty) {
+        contexts.pop();
     }
+    contexts
 }
 
 #[cfg(test)]
@@ -212,11 +834,11 @@ mod tests {
     fn parses_generic() {
         let attr: syn::Attribute = parse_quote!(#[context(C: MyContext + 'static)]);
         assert_eq!(
-            FromContextAttr::parse(attr).map(|attr| attr.context),
-            Ok(ContextImpl::Generic {
+            FromContextAttr::parse(attr).map(|attr| attr.contexts),
+            Ok(vec![ContextImpl::Generic {
                 ident: parse_quote!(C),
                 bounds: parse_quote!(MyContext + 'static),
-            })
+            }])
         );
     }
 
@@ -224,8 +846,8 @@ mod tests {
     fn parses_static() {
         let attr: syn::Attribute = parse_quote!(#[context(MyContext)]);
         assert_eq!(
-            FromContextAttr::parse(attr).map(|attr| attr.context),
-            Ok(ContextImpl::Static(parse_quote!(MyContext)))
+            FromContextAttr::parse(attr).map(|attr| attr.contexts),
+            Ok(vec![ContextImpl::Static(parse_quote!(MyContext))])
         );
     }
 
@@ -242,11 +864,22 @@ mod tests {
         );
     } */
 
+    #[test]
+    fn parses_static_with_generics() {
+        let attr: syn::Attribute = parse_quote!(#[context(crate::Scope<MyContext>)]);
+        assert_eq!(
+            FromContextAttr::parse(attr).map(|attr| attr.contexts),
+            Ok(vec![ContextImpl::Static(parse_quote!(
+                crate::Scope<MyContext>
+            ))])
+        );
+    }
+
     #[test]
     fn detects_no_context() {
         let attr: syn::Attribute = parse_quote!(#[context()]);
         assert_eq!(
-            FromContextAttr::parse(attr.clone()).map(|attr| attr.context),
+            FromContextAttr::parse(attr.clone()).map(|attr| attr.contexts),
             Err(Error {
                 kind: ErrorKind::NoContextIdent,
                 span: attr.span(),
@@ -258,7 +891,7 @@ mod tests {
     fn detects_invalid_context() {
         let attr: syn::Attribute = parse_quote!(#[context(1)]);
         assert_eq!(
-            FromContextAttr::parse(attr.clone()).map(|attr| attr.context),
+            FromContextAttr::parse(attr.clone()).map(|attr| attr.contexts),
             Err(Error {
                 kind: ErrorKind::InvalidContextIdent,
                 span: attr.span(),
@@ -279,14 +912,212 @@ mod tests {
         let result = quote! {
             impl FromContext<MyContext> for Foo {
                 fn from_context (ctx: &MyContext) -> Self {
+                    #[cfg(debug_assertions)]
+                    let _guard = ::cream::context::ResolveGuard::enter::<Self>();
                     Self {
-                        bar: ctx.ctx_provide(),
-                        baz: ctx.ctx_provide()
+                        bar: match ::cream::context::overridden::<String>() {
+                            Some(value) => value,
+                            None => {
+                                use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                                (&::cream::context::Resolver::<String, MyContext>::new()).resolve(ctx)
+                            },
+                        },
+                        baz: match ::cream::context::overridden::<usize>() {
+                            Some(value) => value,
+                            None => {
+                                use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                                (&::cream::context::Resolver::<usize, MyContext>::new()).resolve(ctx)
+                            },
+                        }
+                    }
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    if graph.visit::<Self>() {
+                        graph.depends::<Self, String>();
+                        {
+                            use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                            (&::cream::context::Resolver::<String, MyContext>::new()).dependencies(graph)
+                        };
+                        graph.depends::<Self, usize>();
+                        {
+                            use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                            (&::cream::context::Resolver::<usize, MyContext>::new()).dependencies(graph)
+                        };
                     }
                 }
             }
+
+            impl FromContext<::cream::context::TestContext<MyContext> > for Foo {
+                fn from_context(ctx: &::cream::context::TestContext<MyContext>) -> Self {
+                    ctx.resolve_with(<Self as FromContext<MyContext>>::from_context)
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    <Self as FromContext<MyContext>>::dependencies(graph)
+                }
+            }
         };
 
         assert_eq!(gen_from_context(input).to_string(), result.to_string(),);
     }
+
+    #[test]
+    fn creates_enum_impl() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            #[context(select = ctx.backend)]
+            enum Repo {
+                Memory(MemoryRepo),
+                #[context(when = Backend::Postgres)]
+                Postgres {
+                    pool: Pool,
+                },
+            }
+        );
+
+        let result = quote! {
+            impl FromContext<MyContext> for Repo {
+                fn from_context (ctx: &MyContext) -> Self {
+                    #[cfg(debug_assertions)]
+                    let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                    match ctx.backend {
+                        Backend::Postgres => Self::Postgres {
+                            pool: match ::cream::context::overridden::<Pool>() {
+                                Some(value) => value,
+                                None => {
+                                    use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                                    (&::cream::context::Resolver::<Pool, MyContext>::new()).resolve(ctx)
+                                },
+                            }
+                        },
+                        _ => Self::Memory(match ::cream::context::overridden::<MemoryRepo>() {
+                            Some(value) => value,
+                            None => {
+                                use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                                (&::cream::context::Resolver::<MemoryRepo, MyContext>::new()).resolve(ctx)
+                            },
+                        })
+                    }
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    if graph.visit::<Self>() {
+                        graph.depends::<Self, MemoryRepo>();
+                        {
+                            use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                            (&::cream::context::Resolver::<MemoryRepo, MyContext>::new()).dependencies(graph)
+                        };
+                        graph.depends::<Self, Pool>();
+                        {
+                            use ::cream::context::{ResolveDirect as _, ResolveForwarded as _};
+                            (&::cream::context::Resolver::<Pool, MyContext>::new()).dependencies(graph)
+                        };
+                    }
+                }
+            }
+
+            impl FromContext<::cream::context::TestContext<MyContext> > for Repo {
+                fn from_context(ctx: &::cream::context::TestContext<MyContext>) -> Self {
+                    ctx.resolve_with(<Self as FromContext<MyContext>>::from_context)
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    <Self as FromContext<MyContext>>::dependencies(graph)
+                }
+            }
+        };
+
+        assert_eq!(gen_from_context(input).to_string(), result.to_string());
+    }
+
+    #[test]
+    fn detects_missing_selection() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            enum Repo {
+                Memory(MemoryRepo),
+            }
+        );
+
+        assert!(gen_from_context(input)
+            .to_string()
+            .contains("expected #[context(select = <expr>)]"));
+    }
+
+    #[test]
+    fn detects_generic_types() {
+        let generics: syn::Generics = parse_quote!(<'a, T, const N: usize>);
+
+        assert!(is_generic(&parse_quote!(Box<T>), &generics));
+        assert!(is_generic(&parse_quote!(T::Repo), &generics));
+        assert!(is_generic(&parse_quote!(&'a str), &generics));
+        assert!(is_generic(&parse_quote!([u8; N]), &generics));
+        assert!(!is_generic(&parse_quote!(Tx), &generics));
+        assert!(!is_generic(&parse_quote!(db::T), &generics));
+        assert!(!is_generic(&parse_quote!(&'static str), &generics));
+    }
+
+    #[test]
+    fn merges_generics() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(C: MyContext)]
+            struct Service<'a, R: Repo, const N: usize>
+            where
+                R: Clone,
+            {
+                repo: R,
+                name: &'a str,
+            }
+        );
+        let input = ContextInput::parse(input).unwrap_or_else(|_| panic!("invalid input"));
+        let tys: Vec<_> = field_dependencies(&input.fields, &input.sources).collect();
+
+        let (ctx_name, header) =
+            input.contexts[0].header(quote! { FromContext }, &input.ident, &input.generics, &tys);
+
+        assert_eq!(ctx_name.to_string(), "C");
+        assert_eq!(
+            header.to_string(),
+            quote! {
+                impl<'a, R: Repo, const N: usize, C: MyContext> FromContext<C> for Service<'a, R, N>
+                where
+                    R: Clone,
+                    R: FromContext<C>,
+                    &'a str: FromContext<C>
+            }
+            .to_string()
+        );
+    }
+
+    #[test]
+    fn parses_multiple_contexts() {
+        let attr: syn::Attribute = parse_quote!(#[context(AppContext, crate::Scope<A, B>, C: Fn(u8) -> Box<dyn MyContext>,)]);
+        assert_eq!(
+            FromContextAttr::parse(attr).map(|attr| attr.contexts),
+            Ok(vec![
+                ContextImpl::Static(parse_quote!(AppContext)),
+                ContextImpl::Static(parse_quote!(crate::Scope<A, B>)),
+                ContextImpl::Generic {
+                    ident: parse_quote!(C),
+                    bounds: parse_quote!(Fn(u8) -> Box<dyn MyContext>),
+                },
+            ])
+        );
+    }
+
+    #[test]
+    fn creates_impl_per_context() {
+        let input: syn::DeriveInput = parse_quote!(
+            /// Docs and other attributes are not contexts
+            #[allow(dead_code)]
+            #[context(AppContext)]
+            #[context(TestContext)]
+            struct Foo;
+        );
+
+        let output = gen_from_context(input).to_string();
+        assert!(output.contains(&quote! { impl FromContext<AppContext> for Foo }.to_string()));
+        assert!(output.contains(&quote! { impl FromContext<TestContext> for Foo }.to_string()));
+    }
 }
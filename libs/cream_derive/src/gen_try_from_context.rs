@@ -0,0 +1,316 @@
+use proc_macro2::{Ident, Span, TokenStream};
+use quote::quote;
+
+use crate::{
+    field::FieldSource,
+    gen_from_context::{
+        field_dependencies, gen_dependencies, gen_test_impl, is_generic, overridable, ContextInput,
+    },
+};
+
+pub fn gen_try_from_context(input: syn::DeriveInput) -> TokenStream {
+    let ContextInput {
+        ident,
+        contexts,
+        generics,
+        fields,
+        sources,
+    } = match ContextInput::parse(input) {
+        Ok(input) => input,
+        Err(err) => return err.into(),
+    };
+
+    contexts
+        .iter()
+        .map(|context| {
+            let tys: Vec<_> = field_dependencies(&fields, &sources).collect();
+            let (ctx_name, header) = context.header_with(
+                quote! { TryFromContext },
+                quote! { TryFromContext },
+                &ident,
+                &generics,
+                &tys,
+                error_predicates(&tys, &generics, &context.name()),
+            );
+            let dependencies = gen_dependencies(tys.iter().copied(), |ty| {
+                quote! { <#ty as TryFromContext<#ctx_name>>::dependencies(graph) }
+            });
+
+            let items = gen_items(&ctx_name, &fields, &sources, |ty, source| {
+                overridable(
+                    ty,
+                    &generics,
+                    quote! { Ok(value) },
+                    resolution(ty, source, &ctx_name),
+                )
+            });
+
+            let test_impl = gen_test_impl(
+                context,
+                quote! { TryFromContext },
+                &ident,
+                &generics,
+                quote! {
+                    type Error = <Self as TryFromContext<#ctx_name>>::Error;
+
+                    fn try_from_context(
+                        ctx: &::cream::context::TestContext<#ctx_name>,
+                    ) -> Result<Self, Self::Error> {
+                        ctx.resolve_with(<Self as TryFromContext<#ctx_name>>::try_from_context)
+                    }
+
+                    fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                        <Self as TryFromContext<#ctx_name>>::dependencies(graph)
+                    }
+                },
+            );
+
+            quote! {
+                #header {
+                    #items
+
+                    #dependencies
+                }
+
+                #test_impl
+            }
+        })
+        .collect()
+}
+
+/// Generic fields only report errors that can be boxed
+fn error_predicates(
+    tys: &[&syn::Type],
+    generics: &syn::Generics,
+    ctx_name: &TokenStream,
+) -> Vec<syn::WherePredicate> {
+    tys.iter()
+        .filter(|ty| is_generic(ty, generics))
+        .map(|ty| {
+            syn::parse_quote! {
+                <#ty as TryFromContext<#ctx_name>>::Error:
+                    ::std::convert::Into<Box<dyn ::std::error::Error + Send + Sync>>
+            }
+        })
+        .collect()
+}
+
+/// The `Result` of building a field from `ctx`
+fn resolution(ty: &syn::Type, source: &FieldSource, ctx_name: &TokenStream) -> TokenStream {
+    let resolved_ty = source.dependency(ty).unwrap_or(ty);
+    into_field(
+        quote! { <#resolved_ty as TryFromContext<#ctx_name>>::try_from_context(ctx) },
+        ty,
+        source,
+    )
+}
+
+/// Converts a resolution of a `#[context(from = ...)]` field into its type
+fn into_field(resolution: TokenStream, ty: &syn::Type, source: &FieldSource) -> TokenStream {
+    match source {
+        FieldSource::From(_) => quote! {
+            #resolution.map(|value| -> #ty { ::std::convert::Into::into(value) })
+        },
+        _ => resolution,
+    }
+}
+
+/// The error type and `try_from_context`, collecting the error of every field
+fn gen_items(
+    ctx_name: &TokenStream,
+    fields: &syn::Fields,
+    sources: &[FieldSource],
+    resolution: impl Fn(&syn::Type, &FieldSource) -> TokenStream,
+) -> TokenStream {
+    // (variable, field name used in the error, type, source)
+    let resolved: Vec<_> = fields
+        .iter()
+        .zip(sources)
+        .enumerate()
+        .map(|(i, (field, source))| match &field.ident {
+            Some(name) => (name.clone(), name.to_string(), &field.ty, source),
+            None => (
+                Ident::new(&format!("field_{}", i), Span::call_site()),
+                i.to_string(),
+                &field.ty,
+                source,
+            ),
+        })
+        .collect();
+
+    let resolutions = resolved.iter().map(|(var, name, ty, source)| {
+        if let Some(value) = source.value(quote! { ctx }) {
+            return quote! { let #var = #value; };
+        }
+
+        let resolution = resolution(ty, source);
+        quote! {
+            let #var = errors.field(#name, #resolution);
+        }
+    });
+
+    // Only fields resolved from the context can fail
+    let fallible: Vec<_> = resolved
+        .iter()
+        .filter(|(_, _, _, source)| source.value(quote! { ctx }).is_none())
+        .map(|(var, _, _, _)| var)
+        .collect();
+
+    let vars: Vec<_> = resolved.iter().map(|(var, _, _, _)| var).collect();
+    let build_tokens = match fields {
+        syn::Fields::Named(_) => quote! { Self { #(#vars),* } },
+        syn::Fields::Unnamed(_) => quote! { Self ( #(#vars),* ) },
+        syn::Fields::Unit => quote! { Self },
+    };
+
+    if fallible.is_empty() {
+        return quote! {
+            type Error = ::std::convert::Infallible;
+
+            fn try_from_context(ctx: &#ctx_name) -> Result<Self, Self::Error> {
+                #(#resolutions)*
+                Ok(#build_tokens)
+            }
+        };
+    }
+
+    quote! {
+        type Error = ::cream::context::ResolveError;
+
+        fn try_from_context(ctx: &#ctx_name) -> Result<Self, Self::Error> {
+            #[cfg(debug_assertions)]
+            let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+            let mut errors = ::cream::context::ResolveError::new(::std::any::type_name::<Self>());
+            #(#resolutions)*
+
+            match (#(#fallible,)*) {
+                (#(Some(#fallible),)*) => Ok(#build_tokens),
+                _ => Err(errors),
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn creates_static_impl() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            struct Foo(String);
+        );
+
+        let result = quote! {
+            impl TryFromContext<MyContext> for Foo {
+                type Error = ::cream::context::ResolveError;
+
+                fn try_from_context(ctx: &MyContext) -> Result<Self, Self::Error> {
+                    #[cfg(debug_assertions)]
+                    let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                    let mut errors = ::cream::context::ResolveError::new(::std::any::type_name::<Self>());
+                    let field_0 = errors.field(
+                        "0",
+                        match ::cream::context::overridden::<String>() {
+                            Some(value) => Ok(value),
+                            None => <String as TryFromContext<MyContext>>::try_from_context(ctx),
+                        }
+                    );
+
+                    match (field_0,) {
+                        (Some(field_0),) => Ok(Self(field_0)),
+                        _ => Err(errors),
+                    }
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    if graph.visit::<Self>() {
+                        graph.depends::<Self, String>();
+                        <String as TryFromContext<MyContext>>::dependencies(graph);
+                    }
+                }
+            }
+
+            impl TryFromContext<::cream::context::TestContext<MyContext> > for Foo {
+                type Error = <Self as TryFromContext<MyContext>>::Error;
+
+                fn try_from_context(
+                    ctx: &::cream::context::TestContext<MyContext>,
+                ) -> Result<Self, Self::Error> {
+                    ctx.resolve_with(<Self as TryFromContext<MyContext>>::try_from_context)
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    <Self as TryFromContext<MyContext>>::dependencies(graph)
+                }
+            }
+        };
+
+        assert_eq!(gen_try_from_context(input).to_string(), result.to_string());
+    }
+
+    #[test]
+    fn resolves_only_context_fields() {
+        let input: syn::DeriveInput = parse_quote!(
+            #[context(MyContext)]
+            struct Foo {
+                #[context(from = Config)]
+                settings: Settings,
+                #[context(skip)]
+                marker: PhantomData<u8>,
+            }
+        );
+
+        let result = quote! {
+            impl TryFromContext<MyContext> for Foo {
+                type Error = ::cream::context::ResolveError;
+
+                fn try_from_context(ctx: &MyContext) -> Result<Self, Self::Error> {
+                    #[cfg(debug_assertions)]
+                    let _guard = ::cream::context::ResolveGuard::enter::<Self>();
+                    let mut errors = ::cream::context::ResolveError::new(::std::any::type_name::<Self>());
+                    let settings = errors.field(
+                        "settings",
+                        match ::cream::context::overridden::<Settings>() {
+                            Some(value) => Ok(value),
+                            None => <Config as TryFromContext<MyContext>>::try_from_context(ctx)
+                                .map(|value| -> Settings { ::std::convert::Into::into(value) }),
+                        }
+                    );
+                    let marker = ::std::default::Default::default();
+
+                    match (settings,) {
+                        (Some(settings),) => Ok(Self { settings, marker }),
+                        _ => Err(errors),
+                    }
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    if graph.visit::<Self>() {
+                        graph.depends::<Self, Config>();
+                        <Config as TryFromContext<MyContext>>::dependencies(graph);
+                    }
+                }
+            }
+
+            impl TryFromContext<::cream::context::TestContext<MyContext> > for Foo {
+                type Error = <Self as TryFromContext<MyContext>>::Error;
+
+                fn try_from_context(
+                    ctx: &::cream::context::TestContext<MyContext>,
+                ) -> Result<Self, Self::Error> {
+                    ctx.resolve_with(<Self as TryFromContext<MyContext>>::try_from_context)
+                }
+
+                fn dependencies(graph: &mut ::cream::context::DependencyGraph) {
+                    <Self as TryFromContext<MyContext>>::dependencies(graph)
+                }
+            }
+        };
+
+        assert_eq!(gen_try_from_context(input).to_string(), result.to_string());
+    }
+}
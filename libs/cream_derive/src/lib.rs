@@ -1,5 +1,11 @@
 mod error;
+mod field;
+mod gen_async_from_context;
+mod gen_context;
+mod gen_create_from_context;
 mod gen_from_context;
+mod gen_try_from_context;
+mod select;
 mod common {
     use proc_macro2::TokenStream;
 
@@ -16,3 +22,27 @@ pub fn from_context_derive(input: TokenStream) -> TokenStream {
     let ast = syn::parse_macro_input!(input as syn::DeriveInput);
     gen_from_context::gen_from_context(ast).into()
 }
+
+#[proc_macro_derive(TryFromContext, attributes(context))]
+pub fn try_from_context_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    gen_try_from_context::gen_try_from_context(ast).into()
+}
+
+#[proc_macro_derive(AsyncFromContext, attributes(context))]
+pub fn async_from_context_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    gen_async_from_context::gen_async_from_context(ast).into()
+}
+
+#[proc_macro_derive(CreateFromContext, attributes(context, arg))]
+pub fn create_from_context_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    gen_create_from_context::gen_create_from_context(ast).into()
+}
+
+#[proc_macro_derive(Context, attributes(extend, provide))]
+pub fn context_derive(input: TokenStream) -> TokenStream {
+    let ast = syn::parse_macro_input!(input as syn::DeriveInput);
+    gen_context::gen_context(ast).into()
+}
@@ -0,0 +1,135 @@
+use proc_macro2::{Ident, TokenStream, TokenTree};
+use syn::{parse::Parser, spanned::Spanned};
+
+use crate::{
+    error::{span_compile_error, CompileError},
+    field::FieldSource,
+};
+
+/// What picks the variant of an enum built from a context
+#[derive(Debug)]
+pub(crate) enum Selection {
+    /// `#[context(select = <expr>)]`, evaluated with `ctx` in scope
+    Expr(syn::Expr),
+    /// `#[context(selector = <Type>)]`, resolved from the context
+    Type(syn::Type),
+}
+
+/// An enum variant and the pattern selecting it, `None` for the fallback
+pub(crate) struct SelectVariant {
+    pub ident: Ident,
+    pub when: Option<syn::Pat>,
+    pub fields: syn::Fields,
+    pub sources: Vec<FieldSource>,
+}
+
+impl Selection {
+    /// Parses `select = ...` or `selector = ...`, `None` for any other attribute
+    pub fn parse(attr: &syn::Attribute) -> Option<Result<Self, CompileError>> {
+        let (key, value) = key_value(attr)?;
+        let selection = match key.to_string().as_str() {
+            "select" => syn::parse2(value).map(Self::Expr),
+            "selector" => syn::parse2(value).map(Self::Type),
+            _ => return None,
+        };
+
+        Some(selection.map_err(|err| CompileError(err.to_compile_error())))
+    }
+}
+
+impl SelectVariant {
+    pub fn parse(variant: syn::Variant) -> Result<Self, CompileError> {
+        let mut when = None;
+        for attr in variant
+            .attrs
+            .iter()
+            .filter(|attr| attr.path().is_ident("context"))
+        {
+            let span = attr.span();
+            match key_value(attr) {
+                Some((key, value)) if key == "when" && when.is_none() => {
+                    let pat = syn::Pat::parse_multi_with_leading_vert
+                        .parse2(value)
+                        .map_err(|err| CompileError(err.to_compile_error()))?;
+                    when = Some(pat);
+                }
+                Some((key, _)) if key == "when" => {
+                    return Err(span_compile_error!(span => "duplicate #[context(when = ...)]"))
+                }
+                _ => {
+                    return Err(
+                        span_compile_error!(span => "expected #[context(when = <pattern>)]"),
+                    )
+                }
+            }
+        }
+
+        Ok(Self {
+            ident: variant.ident,
+            when,
+            sources: FieldSource::parse_all(&variant.fields)?,
+            fields: variant.fields,
+        })
+    }
+}
+
+/// Splits `#[context(key = value)]`
+fn key_value(attr: &syn::Attribute) -> Option<(Ident, TokenStream)> {
+    let syn::Meta::List(list) = &attr.meta else {
+        return None;
+    };
+
+    let mut tokens = list.tokens.clone().into_iter();
+    let Some(TokenTree::Ident(key)) = tokens.next() else {
+        return None;
+    };
+    match tokens.next() {
+        Some(TokenTree::Punct(eq))
+            if eq.as_char() == '=' && eq.spacing() == proc_macro2::Spacing::Alone =>
+        {
+            Some((key, tokens.collect()))
+        }
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use syn::parse_quote;
+
+    use super::*;
+
+    #[test]
+    fn parses_selections() {
+        let attr: syn::Attribute = parse_quote!(#[context(select = ctx.config.backend)]);
+        assert!(matches!(
+            Selection::parse(&attr),
+            Some(Ok(Selection::Expr(_)))
+        ));
+
+        let attr: syn::Attribute = parse_quote!(#[context(selector = Backend)]);
+        assert!(matches!(
+            Selection::parse(&attr),
+            Some(Ok(Selection::Type(_)))
+        ));
+
+        let attr: syn::Attribute = parse_quote!(#[context(MyContext)]);
+        assert!(Selection::parse(&attr).is_none());
+    }
+
+    #[test]
+    fn parses_variant_patterns() {
+        let variant: syn::Variant = parse_quote!(
+            #[context(when = Backend::Memory | Backend::Test)]
+            Memory(MemoryRepo)
+        );
+        let variant = SelectVariant::parse(variant).unwrap_or_else(|_| panic!("invalid variant"));
+        assert!(matches!(variant.when, Some(syn::Pat::Or(_))));
+
+        let variant: syn::Variant = parse_quote!(
+            #[context(if = true)]
+            Memory(MemoryRepo)
+        );
+        assert!(SelectVariant::parse(variant).is_err());
+    }
+}
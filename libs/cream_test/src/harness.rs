@@ -0,0 +1,380 @@
+use std::{
+    fmt::Debug,
+    sync::{Arc, Mutex},
+};
+
+use cream::{
+    context::{
+        events_context::{EventsContext, EventsContextBuilder},
+        Context, CreamContext,
+    },
+    event_bus::{BusMetrics, EventBusPort},
+    events::{router::Router, DomainEvent},
+    router_bus::RouterHandle,
+    tasks::{Shutdown, ShutdownReport},
+};
+
+use crate::recording::{assert_published_in, names, of_type, RecordingBus};
+
+/// A router listening on a recorded bus, to drive handlers from tests
+pub struct Harness {
+    cream: CreamContext,
+    events: EventsContext,
+    recording: RecordingBus,
+    failures: Arc<Mutex<Vec<String>>>,
+    router: RouterHandle,
+}
+
+impl Harness {
+    /// Builds the context of the router from fresh cream and events contexts
+    pub fn new<C>(router: Router<C>, ctx: impl FnOnce(&CreamContext, &EventsContext) -> C) -> Self
+    where
+        C: Send + Sync + 'static,
+    {
+        Self::with_builder(EventsContextBuilder::default(), router, ctx)
+    }
+
+    /// The failure handler of `builder` is replaced, the harness keeps the failures
+    pub fn with_builder<C>(
+        builder: EventsContextBuilder,
+        router: Router<C>,
+        ctx: impl FnOnce(&CreamContext, &EventsContext) -> C,
+    ) -> Self
+    where
+        C: Send + Sync + 'static,
+    {
+        let recording = RecordingBus::new();
+        let failures = Arc::new(Mutex::new(Vec::new()));
+
+        let builder = recording.attach(builder).with_failure_handler({
+            let failures = failures.clone();
+            move |failure| failures.lock().unwrap().push(failure.to_string())
+        });
+
+        let cream = CreamContext::default();
+        let (events, setup) = builder.build(&cream);
+        let router = setup.setup(router, ctx(&cream, &events));
+
+        Self {
+            cream,
+            events,
+            recording,
+            failures,
+            router,
+        }
+    }
+
+    pub fn cream(&self) -> &CreamContext {
+        &self.cream
+    }
+
+    pub fn events(&self) -> &EventsContext {
+        &self.events
+    }
+
+    pub fn recording(&self) -> &RecordingBus {
+        &self.recording
+    }
+
+    pub fn router(&self) -> &RouterHandle {
+        &self.router
+    }
+
+    /// Handler errors so far, as `handler failed handling event: error`
+    pub fn failures(&self) -> Vec<String> {
+        self.failures.lock().unwrap().clone()
+    }
+
+    pub fn publish(&self, event: impl DomainEvent + 'static) {
+        self.events.provide::<EventBusPort>().publish(event);
+    }
+
+    /// Runs the router until [`BusMetrics::idle`], so whatever the handlers published is handled
+    /// too
+    pub async fn run_until_quiescent(&self) {
+        self.events.provide::<BusMetrics>().idle().await;
+    }
+
+    /// Starts a scenario with an event that already happened
+    pub fn given(&self, event: impl DomainEvent + 'static) -> Scenario<'_> {
+        Scenario {
+            harness: self,
+            given: Vec::new(),
+        }
+        .given(event)
+    }
+
+    /// Runs a scenario without prior events
+    pub async fn when(&self, event: impl DomainEvent + 'static) -> Then {
+        Scenario {
+            harness: self,
+            given: Vec::new(),
+        }
+        .when(event)
+        .await
+    }
+
+    pub async fn shutdown(self) -> ShutdownReport {
+        self.cream.provide::<Shutdown>().run().await
+    }
+}
+
+/// Events handled before the one under test
+pub struct Scenario<'a> {
+    harness: &'a Harness,
+    given: Vec<Box<dyn DomainEvent>>,
+}
+
+impl Scenario<'_> {
+    pub fn given(mut self, event: impl DomainEvent + 'static) -> Self {
+        self.given.push(Box::new(event));
+        self
+    }
+
+    /// Handles the given events, then `event`, keeping what was published because of it
+    pub async fn when(self, event: impl DomainEvent + 'static) -> Then {
+        let Self { harness, given } = self;
+        let port: EventBusPort = harness.events.provide();
+
+        for event in given {
+            port.publish_boxed(event);
+        }
+        harness.run_until_quiescent().await;
+
+        let seen = harness.recording.len();
+        let failed = harness.failures.lock().unwrap().len();
+        port.publish(event);
+        harness.run_until_quiescent().await;
+
+        // The bus was idle, the first event recorded is the one published here
+        let events = harness
+            .recording
+            .events()
+            .into_iter()
+            .skip(seen + 1)
+            .collect();
+        Then {
+            events,
+            failures: harness.failures()[failed..].to_vec(),
+        }
+    }
+}
+
+/// What handling the event of a [`Scenario`] published
+pub struct Then {
+    events: Vec<Arc<dyn DomainEvent>>,
+    failures: Vec<String>,
+}
+
+impl Then {
+    /// The published `E` are exactly `expected`, in order
+    #[track_caller]
+    pub fn then<E>(self, expected: impl IntoIterator<Item = E>) -> Self
+    where
+        E: DomainEvent + PartialEq + Debug,
+    {
+        let expected: Vec<E> = expected.into_iter().collect();
+        let published: Vec<&E> = of_type::<E>(&self.events).collect();
+
+        assert!(
+            published.iter().copied().eq(expected.iter()),
+            "expected {:?} to be published, got {:?}\nevents: [{}]\nfailures: {:?}",
+            expected,
+            published,
+            names(&self.events),
+            self.failures,
+        );
+        self
+    }
+
+    /// Some published `E` matches
+    #[track_caller]
+    pub fn then_published<E: DomainEvent>(self, matches: impl Fn(&E) -> bool) -> Self {
+        assert_published_in(&self.events, matches);
+        self
+    }
+
+    pub fn events(&self) -> &[Arc<dyn DomainEvent>] {
+        &self.events
+    }
+
+    /// The handler failures while handling the event, the given ones left out
+    pub fn failures(&self) -> &[String] {
+        &self.failures
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use cream::{
+        context::{events_context::EventsContext, Context, FromContext},
+        event_bus::EventBusPort,
+        events::{router::Router, DomainEvent, Error, Handler},
+        router_bus::RouterStatus,
+    };
+
+    use super::*;
+
+    #[derive(Clone, Context)]
+    struct AppContext {
+        #[extend]
+        events: EventsContext,
+        #[provide]
+        placed: Arc<AtomicUsize>,
+    }
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct OrderPlaced(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct ShipOrder(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct OrderShipped(u32);
+
+    #[derive(Clone, Debug, PartialEq)]
+    struct CancelOrder(u32);
+
+    macro_rules! event {
+        ($($event: ident),*) => {
+            $(
+            impl DomainEvent for $event {
+                fn name(&self) -> &'static str {
+                    stringify!($event)
+                }
+
+                fn version(&self) -> &'static str {
+                    "1"
+                }
+            }
+            )*
+        };
+    }
+
+    event!(OrderPlaced, ShipOrder, OrderShipped, CancelOrder);
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct PlacedHandler {
+        placed: Arc<AtomicUsize>,
+    }
+
+    impl Handler for PlacedHandler {
+        type Event = OrderPlaced;
+        async fn handle(&self, _: OrderPlaced) -> Result<(), Error> {
+            self.placed.fetch_add(1, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct ShipHandler {
+        placed: Arc<AtomicUsize>,
+        port: EventBusPort,
+    }
+
+    impl Handler for ShipHandler {
+        type Event = ShipOrder;
+        async fn handle(&self, ShipOrder(id): ShipOrder) -> Result<(), Error> {
+            // Only placed orders ship
+            if self.placed.load(Ordering::SeqCst) > 0 {
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+                self.port.publish(OrderShipped(id));
+            }
+            Ok(())
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct CancelHandler;
+
+    impl Handler for CancelHandler {
+        type Event = CancelOrder;
+        async fn handle(&self, CancelOrder(id): CancelOrder) -> Result<(), Error> {
+            Err(Error::Other(format!("unknown order {}", id).into()))
+        }
+    }
+
+    fn harness() -> Harness {
+        let mut router = Router::<AppContext>::default();
+        router.add::<PlacedHandler>();
+        router.add::<ShipHandler>();
+        router.add::<CancelHandler>();
+
+        Harness::new(router, |_, events| AppContext {
+            events: events.clone(),
+            placed: Arc::default(),
+        })
+    }
+
+    #[tokio::test]
+    async fn runs_scenarios() {
+        let harness = harness();
+
+        harness
+            .given(OrderPlaced(1))
+            .when(ShipOrder(1))
+            .await
+            .then([OrderShipped(1)])
+            .then::<OrderPlaced>([])
+            .then_published::<OrderShipped>(|event| event.0 == 1);
+
+        harness
+            .recording()
+            .assert_published::<OrderPlaced>(|event| event.0 == 1);
+        let router = harness.router().clone();
+        assert!(harness.shutdown().await.is_clean());
+        assert_eq!(router.status(), RouterStatus::Finished);
+    }
+
+    #[tokio::test]
+    async fn keeps_failures_of_the_event() {
+        let harness = harness();
+
+        let then = harness.given(CancelOrder(5)).when(ShipOrder(5)).await;
+        assert!(then.failures().is_empty());
+        assert_eq!(harness.failures().len(), 1);
+
+        let then = harness.when(CancelOrder(6)).await;
+        assert_eq!(then.failures().len(), 1);
+        assert!(then.failures()[0].ends_with("unknown order 6"));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "expected [OrderShipped(2)] to be published, got []")]
+    async fn reports_missing_events() {
+        harness().when(ShipOrder(2)).await.then([OrderShipped(2)]);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
+    async fn records_events_without_handlers() {
+        let harness = harness();
+        harness.publish(OrderShipped(3));
+        harness.run_until_quiescent().await;
+
+        assert_eq!(
+            harness.recording().published::<OrderShipped>(),
+            [OrderShipped(3)]
+        );
+        harness.recording().assert_not_published::<ShipOrder>();
+    }
+
+    #[tokio::test]
+    async fn records_events_published_during_shutdown() {
+        let harness = harness();
+        harness.publish(OrderPlaced(4));
+        harness.run_until_quiescent().await;
+
+        // Shipping outlives the start of the shutdown, so what it publishes is dropped
+        harness.publish(ShipOrder(4));
+        let recording = harness.recording().clone();
+        let report = harness.shutdown().await;
+
+        assert_eq!(report.dropped_events, 1);
+        assert_eq!(recording.published::<OrderShipped>(), [OrderShipped(4)]);
+    }
+}
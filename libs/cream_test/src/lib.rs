@@ -0,0 +1,5 @@
+mod harness;
+mod recording;
+
+pub use harness::*;
+pub use recording::*;
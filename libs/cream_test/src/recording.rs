@@ -0,0 +1,91 @@
+use std::sync::{Arc, Mutex};
+
+use cream::{context::events_context::EventsContextBuilder, events::DomainEvent};
+
+/// Keeps every event published on the bus, in order
+#[derive(Clone, Default)]
+pub struct RecordingBus(Arc<Mutex<Vec<Arc<dyn DomainEvent>>>>);
+
+impl RecordingBus {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Records the events of the bus built by `builder`
+    pub fn attach(&self, builder: EventsContextBuilder) -> EventsContextBuilder {
+        let recording = self.clone();
+        builder.with_publish_observer(move |event| recording.record(event))
+    }
+
+    pub fn record(&self, event: Arc<dyn DomainEvent>) {
+        self.0.lock().unwrap().push(event);
+    }
+
+    pub fn events(&self) -> Vec<Arc<dyn DomainEvent>> {
+        self.0.lock().unwrap().clone()
+    }
+
+    pub fn len(&self) -> usize {
+        self.0.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&self) {
+        self.0.lock().unwrap().clear();
+    }
+
+    /// Every recorded `E`, in order
+    pub fn published<E: DomainEvent + Clone>(&self) -> Vec<E> {
+        of_type::<E>(&self.events()).cloned().collect()
+    }
+
+    /// Panics unless some recorded `E` matches
+    #[track_caller]
+    pub fn assert_published<E: DomainEvent>(&self, matches: impl Fn(&E) -> bool) {
+        assert_published_in(&self.events(), matches);
+    }
+
+    #[track_caller]
+    pub fn assert_not_published<E: DomainEvent>(&self) {
+        let events = self.events();
+        if of_type::<E>(&events).next().is_some() {
+            panic!(
+                "expected no {} to be published, got [{}]",
+                std::any::type_name::<E>(),
+                names(&events)
+            );
+        }
+    }
+}
+
+pub(crate) fn of_type<E: DomainEvent>(events: &[Arc<dyn DomainEvent>]) -> impl Iterator<Item = &E> {
+    events
+        .iter()
+        .filter_map(|event| event.as_any().downcast_ref::<E>())
+}
+
+#[track_caller]
+pub(crate) fn assert_published_in<E: DomainEvent>(
+    events: &[Arc<dyn DomainEvent>],
+    matches: impl Fn(&E) -> bool,
+) {
+    if !of_type::<E>(events).any(matches) {
+        panic!(
+            "expected a matching {} to be published, got [{}]",
+            std::any::type_name::<E>(),
+            names(events)
+        );
+    }
+}
+
+/// `name@version` of every event, for assertion messages
+pub(crate) fn names(events: &[Arc<dyn DomainEvent>]) -> String {
+    events
+        .iter()
+        .map(|event| format!("{}@{}", event.name(), event.version()))
+        .collect::<Vec<_>>()
+        .join(", ")
+}
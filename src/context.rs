@@ -1,5 +1,13 @@
 mod cream_context;
+mod dyn_context;
 pub mod events_context;
+mod forward;
+mod graph;
+mod resolve_error;
+mod scope;
+mod singleton;
+#[cfg(feature = "test-context")]
+mod test_context;
 
 mod helpers {
     #[macro_export]
@@ -15,14 +23,77 @@ mod helpers {
     });
 
     pub use pub_provide;
+
+    /// Marks services of a sub-context as provided by every context extending it
+    ///
+    /// A context can still provide them itself, e.g. with [`pub_provide!`], and that impl wins
+    #[macro_export]
+    macro_rules! pub_export (($provider: path { $($service: path),* $(,)? }) => {
+        $(
+        impl $crate::context::Exported for $service {
+            type Origin = $provider;
+        }
+        )*
+    });
+
+    pub use pub_export;
 }
 
+use std::{convert::Infallible, future::Future};
+
 pub use cream_context::CreamContext;
 pub use cream_derive::*;
+pub use dyn_context::*;
+pub use forward::*;
+pub use graph::*;
 pub use helpers::*;
+pub use resolve_error::*;
+pub use scope::*;
+pub use singleton::*;
+#[cfg(feature = "test-context")]
+pub use test_context::*;
 
 pub trait FromContext<C> {
     fn from_context(ctx: &C) -> Self;
+
+    /// Adds what this service is built from, `#[derive(FromContext)]` fills it with the fields
+    fn dependencies(graph: &mut DependencyGraph)
+    where
+        Self: Sized,
+    {
+        graph.visit::<Self>();
+    }
+}
+
+/// Like [`FromContext`] but for services that may fail to be built, e.g. a missing config value
+pub trait TryFromContext<C>: Sized {
+    type Error;
+    fn try_from_context(ctx: &C) -> Result<Self, Self::Error>;
+
+    fn dependencies(graph: &mut DependencyGraph) {
+        graph.visit::<Self>();
+    }
+}
+
+impl<C, T: FromContext<C>> TryFromContext<C> for T {
+    type Error = Infallible;
+
+    #[inline]
+    fn try_from_context(ctx: &C) -> Result<Self, Self::Error> {
+        Ok(T::from_context(ctx))
+    }
+
+    fn dependencies(graph: &mut DependencyGraph) {
+        <T as FromContext<C>>::dependencies(graph);
+    }
+}
+
+/// Like [`FromContext`] but for services built asynchronously, e.g. a pooled connection
+///
+/// `#[derive(AsyncFromContext)]` builds its fields from either impl, see [`ResolveAsync`], or
+/// from a sub-context exporting them like the other derives
+pub trait AsyncFromContext<C>: Sized {
+    fn from_context_async(ctx: &C) -> impl Future<Output = Self> + Send;
 }
 
 pub trait CreateFromContext<C> {
@@ -52,6 +123,36 @@ pub trait Context {
         self.ctx_provide()
     }
 
+    /// Resolves a service [`Exported`] by a sub-context this context extends
+    ///
+    /// The derives find these on their own, a [`FromContext`] impl for the context winning
+    #[inline]
+    fn provide_exported<S>(&self) -> S
+    where
+        Self: ContextExtend<S::Origin>,
+        S: Exported,
+    {
+        S::from_context(self.provide_ctx())
+    }
+
+    #[inline]
+    fn try_provide<S>(&self) -> Result<S, S::Error>
+    where
+        Self: Sized,
+        S: TryFromContext<Self>,
+    {
+        S::try_from_context(self)
+    }
+
+    #[inline]
+    fn provide_async<S>(&self) -> impl Future<Output = S> + Send
+    where
+        Self: Sized,
+        S: AsyncFromContext<Self>,
+    {
+        S::from_context_async(self)
+    }
+
     #[inline]
     fn create<S>(&self, args: S::Args) -> S
     where
@@ -1,24 +1,90 @@
-use crate::tasks::Tasks;
+use crate::tasks::{CancellationToken, ShutdownSignal, Supervisor, Tasks};
 
-use super::{Context, FromContext};
+use super::{Context, FromContext, Singletons};
 
 #[derive(Clone)]
 pub struct CreamContext {
     tasks: Tasks,
+    signal: ShutdownSignal,
+    singletons: Singletons,
+    supervisor: Supervisor,
 }
 
 impl Default for CreamContext {
     fn default() -> Self {
+        let tasks = Tasks::new();
         Self {
-            tasks: Tasks::new(),
+            supervisor: Supervisor::new(tasks.clone()),
+            tasks,
+            signal: ShutdownSignal::default(),
+            singletons: Singletons::default(),
         }
     }
 }
 
 impl Context for CreamContext {}
 
+crate::pub_export!(CreamContext {
+    Tasks,
+    CancellationToken,
+    ShutdownSignal,
+    Singletons,
+    Supervisor,
+    crate::tasks::Shutdown,
+});
+
 impl FromContext<CreamContext> for Tasks {
     fn from_context(ctx: &CreamContext) -> Self {
         ctx.tasks.clone()
     }
 }
+
+impl FromContext<CreamContext> for CancellationToken {
+    fn from_context(ctx: &CreamContext) -> Self {
+        ctx.tasks.cancellation_token()
+    }
+}
+
+impl FromContext<CreamContext> for ShutdownSignal {
+    fn from_context(ctx: &CreamContext) -> Self {
+        ctx.signal.clone()
+    }
+}
+
+impl FromContext<CreamContext> for Singletons {
+    fn from_context(ctx: &CreamContext) -> Self {
+        ctx.singletons.clone()
+    }
+}
+
+impl FromContext<CreamContext> for Supervisor {
+    fn from_context(ctx: &CreamContext) -> Self {
+        ctx.supervisor.clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::{Context, ContextExtend, CreamContext},
+        tasks::Tasks,
+    };
+
+    #[derive(Clone)]
+    struct Shared<T>(T);
+
+    #[derive(Context)]
+    struct AppContext<T: Clone>(#[extend] CreamContext, #[provide] Shared<T>);
+
+    #[test]
+    fn derives_tuple_contexts() {
+        let ctx = AppContext(CreamContext::default(), Shared("config"));
+
+        let shared: Shared<&str> = ctx.provide();
+        assert_eq!(shared.0, "config");
+
+        let tasks: Tasks = ctx.provide_ctx().provide();
+        tasks.cancel();
+        assert!(ctx.0.tasks.is_cancelled());
+    }
+}
@@ -0,0 +1,222 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    error::Error,
+    fmt,
+    ops::Deref,
+    sync::Arc,
+};
+
+use super::{Context, DependencyGraph, FromContext, ResolveGuard};
+
+type Factory = Box<dyn Fn(&DynContext) -> Arc<dyn Any + Send + Sync> + Send + Sync>;
+
+/// Instances are shared, factories build a new value on every resolution
+enum Service {
+    Instance(Arc<dyn Any + Send + Sync>),
+    Factory(Factory),
+}
+
+/// A context whose services are registered at runtime instead of written as `FromContext` impls,
+/// resolved through [`Provided`]
+#[derive(Clone, Default)]
+pub struct DynContext(Arc<HashMap<TypeId, Service>>);
+
+impl DynContext {
+    pub fn builder() -> DynContextBuilder {
+        DynContextBuilder::default()
+    }
+
+    /// Factories run inside a [`ResolveGuard`], one resolving itself reports the cycle
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let service = match self.0.get(&TypeId::of::<T>())? {
+            Service::Instance(instance) => instance.clone(),
+            Service::Factory(factory) => {
+                // Entered for the wrapper, a factory may run the derived impl of `T` itself
+                let _guard = ResolveGuard::enter::<Provided<T>>();
+                factory(self)
+            }
+        };
+
+        Some(
+            service
+                .downcast::<T>()
+                .expect("services are stored by their type id"),
+        )
+    }
+
+    pub fn try_get<T: Send + Sync + 'static>(&self) -> Result<Arc<T>, NotRegistered> {
+        self.get().ok_or(NotRegistered {
+            service: std::any::type_name::<T>(),
+        })
+    }
+
+    pub fn contains<T: 'static>(&self) -> bool {
+        self.0.contains_key(&TypeId::of::<T>())
+    }
+}
+
+impl Context for DynContext {}
+
+#[derive(Default)]
+pub struct DynContextBuilder(HashMap<TypeId, Service>);
+
+impl DynContextBuilder {
+    /// Shared by every resolution, without cloning it
+    pub fn with_instance<T: Send + Sync + 'static>(mut self, instance: T) -> Self {
+        let instance = Service::Instance(Arc::new(instance));
+        self.0.insert(TypeId::of::<T>(), instance);
+        self
+    }
+
+    /// Called on every resolution, may resolve other services from the context
+    pub fn with_factory<T, F>(mut self, factory: F) -> Self
+    where
+        T: Send + Sync + 'static,
+        F: Fn(&DynContext) -> T + Send + Sync + 'static,
+    {
+        let factory: Factory = Box::new(move |ctx| Arc::new(factory(ctx)));
+        self.0.insert(TypeId::of::<T>(), Service::Factory(factory));
+        self
+    }
+
+    pub fn build(self) -> DynContext {
+        DynContext(Arc::new(self.0))
+    }
+}
+
+/// A service missing from a [`DynContext`]
+#[derive(Debug)]
+pub struct NotRegistered {
+    pub service: &'static str,
+}
+
+impl fmt::Display for NotRegistered {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} is not registered in the DynContext", self.service)
+    }
+}
+
+impl Error for NotRegistered {}
+
+/// A service registered in a [`DynContext`], so `#[derive(FromContext)]` structs resolve from it
+///
+/// Resolving a service that is not registered panics with [`NotRegistered`], check first with
+/// [`DynContext::try_get`] when it is optional
+pub struct Provided<T>(Arc<T>);
+
+impl<T> Provided<T> {
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> Clone for Provided<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Provided<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<T: Send + Sync + 'static> FromContext<DynContext> for Provided<T> {
+    fn from_context(ctx: &DynContext) -> Self {
+        match ctx.try_get() {
+            Ok(service) => Provided(service),
+            Err(err) => panic!("{}", err),
+        }
+    }
+
+    /// What a factory resolves is not known, only the registered service is declared
+    fn dependencies(graph: &mut DependencyGraph) {
+        if graph.visit::<Self>() {
+            graph.depends::<Self, T>();
+            graph.visit::<T>();
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::context::{Context, DynContext, FromContext, Provided};
+
+    struct Config {
+        url: &'static str,
+    }
+
+    struct Repo {
+        url: &'static str,
+    }
+
+    #[derive(FromContext)]
+    #[context(DynContext)]
+    struct Service {
+        config: Provided<Config>,
+        repo: Provided<Repo>,
+    }
+
+    #[test]
+    fn resolves_registered_services() {
+        let ctx = DynContext::builder()
+            .with_instance(Config { url: "pg://db" })
+            .with_factory(|ctx| Repo {
+                url: ctx.get::<Config>().unwrap().url,
+            })
+            .build();
+
+        let service: Service = ctx.provide();
+        assert_eq!(service.config.url, "pg://db");
+        assert_eq!(service.repo.url, "pg://db");
+        assert!(!ctx.contains::<Service>());
+
+        // Instances are shared, not cloned
+        let again: Service = ctx.provide();
+        assert!(Arc::ptr_eq(
+            &service.config.into_inner(),
+            &again.config.into_inner()
+        ));
+    }
+
+    #[test]
+    #[should_panic(expected = "Repo is not registered in the DynContext")]
+    fn reports_missing_services() {
+        let ctx = DynContext::builder()
+            .with_instance(Config { url: "pg://db" })
+            .build();
+
+        assert!(ctx.try_get::<Repo>().is_err());
+        let _: Service = ctx.provide();
+    }
+
+    #[test]
+    fn runs_factories_of_derived_services() {
+        let ctx = DynContext::builder()
+            .with_instance(Config { url: "pg://db" })
+            .with_instance(Repo { url: "pg://repo" })
+            .with_factory(Service::from_context)
+            .build();
+
+        let service = ctx.get::<Service>().unwrap();
+        assert_eq!(service.repo.url, "pg://repo");
+    }
+
+    #[test]
+    #[should_panic(expected = "dependency cycle detected")]
+    fn detects_factories_resolving_themselves() {
+        let ctx = DynContext::builder()
+            .with_factory(|ctx| Repo {
+                url: ctx.get::<Repo>().unwrap().url,
+            })
+            .build();
+
+        ctx.get::<Repo>();
+    }
+}
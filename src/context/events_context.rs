@@ -1,7 +1,11 @@
+use std::{sync::Arc, time::Duration};
+
 use crate::{
-    event_bus::{EventBusPort, EventBusSocket},
-    events::router::Router,
-    router_bus::RouterBus,
+    event_bus::{BusMetrics, EventBusPort, EventBusSocket},
+    events::{router::Router, DomainEvent, Failure, Handler},
+    router_bus::{
+        log_failure, ConcurrencyLimits, EventObserver, FailureHandler, RouterBus, RouterHandle,
+    },
     tasks::Tasks,
 };
 
@@ -14,19 +18,42 @@ pub struct EventsContext {
 
 impl Context for EventsContext {}
 
+crate::pub_export!(EventsContext {
+    EventBusPort,
+    BusMetrics
+});
+
 impl FromContext<EventsContext> for EventBusPort {
     fn from_context(ctx: &EventsContext) -> Self {
         ctx.port.clone()
     }
 }
 
+impl FromContext<EventsContext> for BusMetrics {
+    fn from_context(ctx: &EventsContext) -> Self {
+        ctx.port.metrics().clone()
+    }
+}
+
 pub struct EventsContextBuilder {
     channel_size: usize,
+    limits: ConcurrencyLimits,
+    handler_timeout: Option<Duration>,
+    on_failure: FailureHandler,
+    on_event: Option<EventObserver>,
+    on_publish: Option<EventObserver>,
 }
 
 impl Default for EventsContextBuilder {
     fn default() -> Self {
-        Self { channel_size: 10 }
+        Self {
+            channel_size: 10,
+            limits: ConcurrencyLimits::default(),
+            handler_timeout: None,
+            on_failure: Arc::new(log_failure),
+            on_event: None,
+            on_publish: None,
+        }
     }
 }
 
@@ -36,15 +63,67 @@ impl EventsContextBuilder {
         self
     }
 
+    /// Max handler calls running at the same time across the whole router, the router stops
+    /// taking events from the bus while they are all taken
+    pub fn with_concurrency_limit(mut self, limit: usize) -> Self {
+        self.limits = self.limits.with_global(limit);
+        self
+    }
+
+    /// Max calls of `H` running at the same time
+    pub fn with_handler_concurrency_limit<H: Handler + 'static>(mut self, limit: usize) -> Self {
+        self.limits = self.limits.with_handler::<H>(limit);
+        self
+    }
+
+    /// Default timeout for every handler, overriden per handler with
+    /// [`HandlerOptions::with_timeout`](crate::events::router::HandlerOptions::with_timeout)
+    pub fn with_handler_timeout(mut self, timeout: Duration) -> Self {
+        self.handler_timeout = Some(timeout);
+        self
+    }
+
+    /// Called with every handler error instead of just logging it
+    pub fn with_failure_handler(mut self, f: impl Fn(Failure) + Send + Sync + 'static) -> Self {
+        self.on_failure = Arc::new(f);
+        self
+    }
+
+    /// Called with every event the router takes from the bus, even those without handlers
+    pub fn with_event_observer(
+        mut self,
+        f: impl Fn(Arc<dyn DomainEvent>) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_event = Some(Arc::new(f));
+        self
+    }
+
+    /// Called with every event published on the bus, even those dropped by the shutdown
+    pub fn with_publish_observer(
+        mut self,
+        f: impl Fn(Arc<dyn DomainEvent>) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_publish = Some(Arc::new(f));
+        self
+    }
+
     pub fn build(self, cream_ctx: &CreamContext) -> (EventsContext, EventsContextSetup) {
         let tasks: Tasks = cream_ctx.provide();
         let (port, socket) = {
             let tasks = cream_ctx.provide();
-            crate::event_bus::create(self.channel_size, tasks)
+            let signal = cream_ctx.provide();
+            crate::event_bus::create(self.channel_size, tasks, signal, self.on_publish)
         };
 
         let ctx = EventsContext { port };
-        let setup = EventsContextSetup { socket, tasks };
+        let setup = EventsContextSetup {
+            socket,
+            tasks,
+            limits: self.limits,
+            handler_timeout: self.handler_timeout,
+            on_failure: self.on_failure,
+            on_event: self.on_event,
+        };
 
         (ctx, setup)
     }
@@ -53,11 +132,20 @@ impl EventsContextBuilder {
 pub struct EventsContextSetup {
     socket: EventBusSocket,
     tasks: Tasks,
+    limits: ConcurrencyLimits,
+    handler_timeout: Option<Duration>,
+    on_failure: FailureHandler,
+    on_event: Option<EventObserver>,
 }
 
 impl EventsContextSetup {
-    pub fn setup<C: Send + 'static + Sync>(self, router: Router<C>, ctx: C) {
-        let mut bus = RouterBus::new(self.socket, ctx, router, self.tasks);
-        tokio::spawn(async move { bus.listen().await });
+    /// Starts the router listening on the bus, see [`RouterHandle`]
+    pub fn setup<C: Send + 'static + Sync>(self, router: Router<C>, ctx: C) -> RouterHandle {
+        RouterBus::new(self.socket, ctx, router, self.tasks)
+            .with_limits(&self.limits)
+            .with_handler_timeout(self.handler_timeout)
+            .with_failure_handler(self.on_failure)
+            .with_event_observer(self.on_event)
+            .start()
     }
 }
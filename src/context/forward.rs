@@ -0,0 +1,243 @@
+use std::{future::Future, marker::PhantomData};
+
+use super::{AsyncFromContext, Context, ContextExtend, DependencyGraph, FromContext};
+
+/// A service of a sub-context that every context extending it provides too
+///
+/// Use [`pub_export!`](crate::pub_export) rather than implementing it by hand. Derived fields
+/// whose type uses a generic parameter are bound by [`FromContext`] and don't find these
+pub trait Exported: FromContext<Self::Origin> + Sized {
+    type Origin: Context;
+}
+
+/// Resolves `S` from `C` for the derives, from the context itself or from a sub-context exporting it
+///
+/// Called as `(&Resolver::<S, C>::new()).resolve(ctx)` with both traits in scope, method lookup
+/// tries [`ResolveDirect`] on `Resolver` before [`ResolveForwarded`] on `&Resolver`, so a
+/// [`FromContext`] impl for the context wins over an [`Exported`] one
+pub struct Resolver<S, C>(PhantomData<fn(&C) -> S>);
+
+impl<S, C> Resolver<S, C> {
+    pub fn new() -> Self {
+        Resolver(PhantomData)
+    }
+}
+
+impl<S, C> Default for Resolver<S, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`Resolver`] of services with a [`FromContext`] impl for the context
+pub trait ResolveDirect<S, C> {
+    fn resolve(&self, ctx: &C) -> S;
+
+    fn dependencies(&self, graph: &mut DependencyGraph);
+}
+
+impl<S: FromContext<C>, C> ResolveDirect<S, C> for Resolver<S, C> {
+    #[inline]
+    fn resolve(&self, ctx: &C) -> S {
+        S::from_context(ctx)
+    }
+
+    fn dependencies(&self, graph: &mut DependencyGraph) {
+        S::dependencies(graph)
+    }
+}
+
+/// [`Resolver`] of [`Exported`] services, built from the sub-context the context extends
+pub trait ResolveForwarded<S, C> {
+    fn resolve(&self, ctx: &C) -> S;
+
+    fn dependencies(&self, graph: &mut DependencyGraph);
+}
+
+impl<S, C> ResolveForwarded<S, C> for &Resolver<S, C>
+where
+    S: Exported,
+    C: ContextExtend<S::Origin>,
+{
+    #[inline]
+    fn resolve(&self, ctx: &C) -> S {
+        S::from_context(ctx.provide_ctx())
+    }
+
+    fn dependencies(&self, graph: &mut DependencyGraph) {
+        <S as FromContext<S::Origin>>::dependencies(graph)
+    }
+}
+
+/// [`ResolveAsync`] route for services built from the context itself
+pub struct Direct;
+
+/// [`ResolveAsync`] route for services with an [`AsyncFromContext`] impl
+pub struct Awaited;
+
+/// How `#[derive(AsyncFromContext)]` gets its fields, built synchronously or awaited
+///
+/// The route is inferred, a service implementing both is ambiguous
+pub trait ResolveAsync<C, Route>: Sized {
+    fn resolve_async(ctx: &C) -> impl Future<Output = Self> + Send;
+}
+
+impl<C, S: FromContext<C> + Send> ResolveAsync<C, Direct> for S {
+    #[inline]
+    fn resolve_async(ctx: &C) -> impl Future<Output = Self> + Send {
+        std::future::ready(S::from_context(ctx))
+    }
+}
+
+impl<C, S: AsyncFromContext<C>> ResolveAsync<C, Awaited> for S {
+    #[inline]
+    fn resolve_async(ctx: &C) -> impl Future<Output = Self> + Send {
+        S::from_context_async(ctx)
+    }
+}
+
+/// Resolves `S` from `C` for `#[derive(AsyncFromContext)]`, like [`Resolver`] with the services
+/// of the context found through [`ResolveAsync`]
+///
+/// Called as `(&AsyncResolver::<S, C>::new()).resolve_async(ctx)` with both traits in scope
+pub struct AsyncResolver<S, C>(PhantomData<fn(&C) -> S>);
+
+impl<S, C> AsyncResolver<S, C> {
+    pub fn new() -> Self {
+        AsyncResolver(PhantomData)
+    }
+}
+
+impl<S, C> Default for AsyncResolver<S, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`AsyncResolver`] of services with either impl for the context
+pub trait AsyncResolveDirect<S, C, Route> {
+    fn resolve_async(&self, ctx: &C) -> impl Future<Output = S> + Send;
+}
+
+impl<S: ResolveAsync<C, Route>, C, Route> AsyncResolveDirect<S, C, Route> for AsyncResolver<S, C> {
+    #[inline]
+    fn resolve_async(&self, ctx: &C) -> impl Future<Output = S> + Send {
+        S::resolve_async(ctx)
+    }
+}
+
+/// [`AsyncResolver`] of [`Exported`] services, built from the sub-context the context extends
+pub trait AsyncResolveForwarded<S, C> {
+    fn resolve_async(&self, ctx: &C) -> impl Future<Output = S> + Send;
+}
+
+impl<S, C> AsyncResolveForwarded<S, C> for &AsyncResolver<S, C>
+where
+    S: Exported + Send,
+    C: ContextExtend<S::Origin>,
+{
+    #[inline]
+    fn resolve_async(&self, ctx: &C) -> impl Future<Output = S> + Send {
+        std::future::ready(S::from_context(ctx.provide_ctx()))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::{
+        context::{AsyncFromContext, Context, ContextExtend, CreamContext, FromContext},
+        tasks::{ShutdownSignal, Tasks},
+    };
+
+    #[derive(Default)]
+    struct AppContext {
+        cream: CreamContext,
+    }
+
+    impl Context for AppContext {}
+
+    impl ContextExtend<CreamContext> for AppContext {
+        fn provide_ctx(&self) -> &CreamContext {
+            &self.cream
+        }
+    }
+
+    // Foreign types can still be provided directly
+    impl FromContext<AppContext> for String {
+        fn from_context(_: &AppContext) -> Self {
+            "app".to_string()
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct Service {
+        tasks: Tasks,
+        name: String,
+    }
+
+    #[test]
+    fn forwards_exported_services() {
+        let ctx = AppContext::default();
+        let service: Service = ctx.provide();
+        let tasks: Tasks = ctx.provide_exported();
+
+        assert_eq!(service.name, "app");
+        service.tasks.cancel();
+        assert!(tasks.is_cancelled());
+    }
+
+    #[derive(Default, Context)]
+    struct ProvidingContext {
+        #[extend]
+        cream: CreamContext,
+    }
+
+    crate::pub_provide!(ProvidingContext : CreamContext { Tasks });
+
+    #[derive(FromContext)]
+    #[context(ProvidingContext)]
+    struct Both {
+        tasks: Tasks,
+        _signal: ShutdownSignal,
+    }
+
+    #[test]
+    fn prefers_provided_over_exported_services() {
+        let ctx = ProvidingContext::default();
+        let both: Both = ctx.provide();
+        let tasks = ctx.provide::<Tasks>();
+
+        both.tasks.cancel();
+        assert!(tasks.is_cancelled());
+        assert!(ctx.cream.provide::<Tasks>().is_cancelled());
+    }
+
+    #[derive(AsyncFromContext)]
+    #[context(AppContext)]
+    struct AsyncService {
+        tasks: Tasks,
+        name: String,
+    }
+
+    #[derive(AsyncFromContext)]
+    #[context(ProvidingContext)]
+    struct AsyncBoth {
+        tasks: Tasks,
+        _signal: ShutdownSignal,
+    }
+
+    #[tokio::test]
+    async fn forwards_exported_services_async() {
+        let ctx = AppContext::default();
+        let service: AsyncService = ctx.provide_async().await;
+        assert_eq!(service.name, "app");
+        service.tasks.cancel();
+        assert!(ctx.provide_exported::<Tasks>().is_cancelled());
+
+        let ctx = ProvidingContext::default();
+        let both: AsyncBoth = ctx.provide_async().await;
+        both.tasks.cancel();
+        assert!(ctx.provide::<Tasks>().is_cancelled());
+    }
+}
@@ -0,0 +1,261 @@
+use std::{cell::RefCell, collections::BTreeSet, fmt::Write, future::Future};
+
+use super::TryFromContext;
+
+thread_local! {
+    static RESOLVING: RefCell<Vec<&'static str>> = const { RefCell::new(Vec::new()) };
+}
+
+/// Tracks the services being built on this thread, panicking with the dependency path when one
+/// of them ends up depending on itself instead of overflowing the stack.
+///
+/// `#[derive(FromContext)]` enters it on debug builds
+pub struct ResolveGuard(());
+
+impl ResolveGuard {
+    pub fn enter<T: ?Sized>() -> Self {
+        let name = std::any::type_name::<T>();
+        let cycle = RESOLVING.with(|resolving| {
+            let mut resolving = resolving.borrow_mut();
+            let start = resolving.iter().position(|resolving| *resolving == name);
+            let cycle = start.map(|start| {
+                let mut path = resolving[start..].to_vec();
+                path.push(name);
+                path.join(" -> ")
+            });
+
+            if cycle.is_none() {
+                resolving.push(name);
+            }
+
+            cycle
+        });
+
+        if let Some(cycle) = cycle {
+            panic!("dependency cycle detected: {}", cycle);
+        }
+
+        ResolveGuard(())
+    }
+
+    /// Like [`enter`](Self::enter) for a service built by `fut`, entered whenever it is polled
+    ///
+    /// `#[derive(AsyncFromContext)]` wraps its future with it on debug builds
+    pub async fn enter_async<T: ?Sized, F: Future>(fut: F) -> F::Output {
+        let mut fut = std::pin::pin!(fut);
+        std::future::poll_fn(|cx| {
+            let _guard = Self::enter::<T>();
+            fut.as_mut().poll(cx)
+        })
+        .await
+    }
+}
+
+impl Drop for ResolveGuard {
+    fn drop(&mut self) {
+        RESOLVING.with(|resolving| resolving.borrow_mut().pop());
+    }
+}
+
+/// Services and what they are built from, as declared by `#[derive(FromContext)]`
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct DependencyGraph {
+    nodes: BTreeSet<&'static str>,
+    edges: BTreeSet<(&'static str, &'static str)>,
+}
+
+impl DependencyGraph {
+    pub fn of<C, T: TryFromContext<C>>() -> Self {
+        let mut graph = Self::default();
+        graph.add::<C, T>();
+        graph
+    }
+
+    pub fn add<C, T: TryFromContext<C>>(&mut self) -> &mut Self {
+        T::dependencies(self);
+        self.visit::<T>();
+        self
+    }
+
+    /// Adds the node, returning false if it was already there
+    pub fn visit<T: ?Sized>(&mut self) -> bool {
+        self.nodes.insert(std::any::type_name::<T>())
+    }
+
+    /// Adds the edge, the dependency is only marked visited once its own dependencies are added
+    pub fn depends<T: ?Sized, D: ?Sized>(&mut self) {
+        let (service, dependency) = (std::any::type_name::<T>(), std::any::type_name::<D>());
+        self.nodes.insert(service);
+        self.edges.insert((service, dependency));
+    }
+
+    pub fn nodes(&self) -> impl Iterator<Item = &'static str> + '_ {
+        self.nodes.iter().copied()
+    }
+
+    pub fn edges(&self) -> impl Iterator<Item = (&'static str, &'static str)> + '_ {
+        self.edges.iter().copied()
+    }
+
+    /// The first dependency path found going back to where it started
+    pub fn find_cycle(&self) -> Option<Vec<&'static str>> {
+        fn visit(
+            graph: &DependencyGraph,
+            node: &'static str,
+            path: &mut Vec<&'static str>,
+            done: &mut BTreeSet<&'static str>,
+        ) -> Option<Vec<&'static str>> {
+            if let Some(start) = path.iter().position(|visited| *visited == node) {
+                let mut cycle = path[start..].to_vec();
+                cycle.push(node);
+                return Some(cycle);
+            }
+
+            if !done.insert(node) {
+                return None;
+            }
+
+            path.push(node);
+            let dependencies = graph.edges.iter().filter(|(from, _)| *from == node);
+            for (_, dependency) in dependencies {
+                if let Some(cycle) = visit(graph, dependency, path, done) {
+                    return Some(cycle);
+                }
+            }
+            path.pop();
+
+            None
+        }
+
+        let mut done = BTreeSet::new();
+        self.nodes
+            .iter()
+            .find_map(|node| visit(self, node, &mut vec![], &mut done))
+    }
+
+    pub fn to_dot(&self) -> String {
+        let mut dot = String::from("digraph dependencies {\n");
+        for node in &self.nodes {
+            let _ = writeln!(dot, "    {:?};", node);
+        }
+        for (service, dependency) in &self.edges {
+            let _ = writeln!(dot, "    {:?} -> {:?};", service, dependency);
+        }
+        dot.push('}');
+        dot
+    }
+
+    pub fn to_json(&self) -> String {
+        let nodes: Vec<_> = self
+            .nodes
+            .iter()
+            .map(|node| format!("{:?}", node))
+            .collect();
+        let edges: Vec<_> = self
+            .edges
+            .iter()
+            .map(|(service, dependency)| {
+                format!("{{\"from\":{:?},\"to\":{:?}}}", service, dependency)
+            })
+            .collect();
+
+        format!(
+            "{{\"nodes\":[{}],\"edges\":[{}]}}",
+            nodes.join(","),
+            edges.join(",")
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::context::{Context, DependencyGraph, FromContext, ResolveGuard};
+
+    struct Ctx;
+    impl Context for Ctx {}
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct Leaf;
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct Service {
+        _leaf: Leaf,
+    }
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct Chicken {
+        _egg: Egg,
+    }
+
+    struct Egg;
+    impl FromContext<Ctx> for Egg {
+        fn from_context(ctx: &Ctx) -> Self {
+            let _: Chicken = ctx.provide();
+            Egg
+        }
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dependency cycle detected")]
+    fn detects_cycles_while_resolving() {
+        let _: Chicken = Ctx.provide();
+    }
+
+    #[tokio::test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "dependency cycle detected")]
+    async fn detects_cycles_while_resolving_async() {
+        let fut = async {
+            tokio::task::yield_now().await;
+            let _: Chicken = Ctx.provide();
+        };
+        ResolveGuard::enter_async::<Chicken, _>(fut).await;
+    }
+
+    #[test]
+    fn dumps_the_graph() {
+        let graph = DependencyGraph::of::<Ctx, Service>();
+
+        let service = std::any::type_name::<Service>();
+        let leaf = std::any::type_name::<Leaf>();
+        assert_eq!(graph.edges().collect::<Vec<_>>(), vec![(service, leaf)]);
+        assert!(graph.find_cycle().is_none());
+
+        assert!(graph
+            .to_dot()
+            .contains(&format!("{:?} -> {:?};", service, leaf)));
+        assert!(graph
+            .to_json()
+            .contains(&format!("{{\"from\":{:?},\"to\":{:?}}}", service, leaf)));
+    }
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct App {
+        _service: Service,
+    }
+
+    #[test]
+    fn walks_transitive_dependencies() {
+        let graph = DependencyGraph::of::<Ctx, App>();
+
+        let leaf = std::any::type_name::<Leaf>();
+        assert!(graph.nodes().any(|node| node == leaf));
+        assert_eq!(graph.edges().count(), 2);
+    }
+
+    #[test]
+    fn finds_cycles_in_the_graph() {
+        let mut graph = DependencyGraph::default();
+        graph.depends::<Chicken, Egg>();
+        graph.depends::<Egg, Chicken>();
+
+        let cycle = graph.find_cycle().unwrap();
+        assert_eq!(cycle.len(), 3);
+        assert_eq!(cycle.first(), cycle.last());
+    }
+}
@@ -0,0 +1,114 @@
+use std::{error::Error, fmt};
+
+/// Every field that failed while building a service with `#[derive(TryFromContext)]`
+#[derive(Debug)]
+pub struct ResolveError {
+    service: &'static str,
+    fields: Vec<FieldError>,
+}
+
+#[derive(Debug)]
+pub struct FieldError {
+    pub field: &'static str,
+    pub error: Box<dyn Error + Send + Sync>,
+}
+
+impl ResolveError {
+    pub fn new(service: &'static str) -> Self {
+        Self {
+            service,
+            fields: Vec::new(),
+        }
+    }
+
+    pub fn service(&self) -> &'static str {
+        self.service
+    }
+
+    pub fn fields(&self) -> &[FieldError] {
+        &self.fields
+    }
+
+    /// Keeps the error of a field, if any, so all of them are reported at once
+    pub fn field<T, E>(&mut self, field: &'static str, result: Result<T, E>) -> Option<T>
+    where
+        E: Into<Box<dyn Error + Send + Sync>>,
+    {
+        match result {
+            Ok(value) => Some(value),
+            Err(error) => {
+                self.fields.push(FieldError {
+                    field,
+                    error: error.into(),
+                });
+                None
+            }
+        }
+    }
+}
+
+impl fmt::Display for ResolveError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "failed to resolve {}", self.service)?;
+        for (i, field) in self.fields.iter().enumerate() {
+            let sep = if i == 0 { ": " } else { ", " };
+            write!(f, "{}`{}`: {}", sep, field.field, field.error)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Error for ResolveError {}
+
+#[cfg(test)]
+mod tests {
+    use std::{error::Error, fmt};
+
+    use crate::context::{Context, TryFromContext};
+
+    struct Ctx {
+        configured: bool,
+    }
+
+    impl Context for Ctx {}
+
+    #[derive(Debug)]
+    struct NotConfigured;
+
+    impl fmt::Display for NotConfigured {
+        fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+            write!(f, "not configured")
+        }
+    }
+
+    impl Error for NotConfigured {}
+
+    struct Repo;
+
+    impl TryFromContext<Ctx> for Repo {
+        type Error = NotConfigured;
+
+        fn try_from_context(ctx: &Ctx) -> Result<Self, Self::Error> {
+            ctx.configured.then_some(Repo).ok_or(NotConfigured)
+        }
+    }
+
+    #[derive(TryFromContext)]
+    #[context(Ctx)]
+    struct TryService<R> {
+        _repo: R,
+    }
+
+    #[test]
+    fn reports_errors_of_generic_fields() {
+        assert!(TryService::<Repo>::try_from_context(&Ctx { configured: true }).is_ok());
+
+        let Err(error) = TryService::<Repo>::try_from_context(&Ctx { configured: false }) else {
+            panic!("the repo is not configured");
+        };
+        assert_eq!(error.fields().len(), 1);
+        assert_eq!(error.fields()[0].field, "_repo");
+        assert_eq!(error.fields()[0].error.to_string(), "not configured");
+    }
+}
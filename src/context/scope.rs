@@ -0,0 +1,194 @@
+use std::{
+    any::{type_name, Any, TypeId},
+    collections::HashMap,
+    marker::PhantomData,
+    ops::Deref,
+    sync::{Arc, Mutex},
+};
+
+use super::{Context, ContextExtend, DependencyGraph, FromContext};
+
+/// A child of `C` living for a single event dispatch, holding values only that dispatch sees,
+/// like a transaction, a tenant or the event itself
+///
+/// The router keeps it until the last scoped handler of the event is done
+pub struct Scope<C> {
+    parent: Arc<C>,
+    values: Mutex<HashMap<TypeId, Arc<dyn Any + Send + Sync>>>,
+}
+
+impl<C> Scope<C> {
+    pub fn new(parent: C) -> Self {
+        Self::shared(Arc::new(parent))
+    }
+
+    /// A scope of a context shared with other owners, like the router's
+    pub fn shared(parent: Arc<C>) -> Self {
+        Self {
+            parent,
+            values: Mutex::default(),
+        }
+    }
+
+    pub fn parent(&self) -> &C {
+        &self.parent
+    }
+
+    pub fn insert<T: Send + Sync + 'static>(&self, value: T) {
+        self.insert_shared(Arc::new(value));
+    }
+
+    fn insert_shared<T: Send + Sync + 'static>(&self, value: Arc<T>) {
+        self.values.lock().unwrap().insert(TypeId::of::<T>(), value);
+    }
+
+    pub fn get<T: Send + Sync + 'static>(&self) -> Option<Arc<T>> {
+        let value = self
+            .values
+            .lock()
+            .unwrap()
+            .get(&TypeId::of::<T>())
+            .cloned()?;
+
+        value.downcast().ok()
+    }
+}
+
+impl<C: Context> Context for Scope<C> {}
+
+impl<C: Context> ContextExtend<C> for Scope<C> {
+    fn provide_ctx(&self) -> &C {
+        &self.parent
+    }
+}
+
+/// Resolves `T` from the scope first, falling back to the parent context.
+///
+/// What comes from the parent is kept in the scope, so it is built once per dispatch and shared
+/// by its handlers. Values the parent can't build are resolved with [`InScope`]
+pub struct Scoped<T>(pub Arc<T>);
+
+impl<T> Scoped<T> {
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for Scoped<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<C, T> FromContext<Scope<C>> for Scoped<T>
+where
+    T: FromContext<C> + Send + Sync + 'static,
+{
+    fn from_context(scope: &Scope<C>) -> Self {
+        if let Some(value) = scope.get::<T>() {
+            return Scoped(value);
+        }
+
+        let value = Arc::new(T::from_context(&scope.parent));
+        scope.insert_shared(value.clone());
+        Scoped(value)
+    }
+
+    fn dependencies(graph: &mut DependencyGraph) {
+        if graph.visit::<Self>() {
+            graph.depends::<Self, T>();
+            T::dependencies(graph);
+        }
+    }
+}
+
+/// Resolves `T` from the scope only, for values the parent context can't build like a
+/// transaction
+///
+/// Panics when nothing inserted it in the scope
+pub struct InScope<T>(pub Arc<T>);
+
+impl<T> InScope<T> {
+    pub fn into_inner(self) -> Arc<T> {
+        self.0
+    }
+}
+
+impl<T> Deref for InScope<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<C, T: Send + Sync + 'static> FromContext<Scope<C>> for InScope<T> {
+    fn from_context(scope: &Scope<C>) -> Self {
+        match scope.get::<T>() {
+            Some(value) => InScope(value),
+            None => panic!("{} is not in the scope", type_name::<T>()),
+        }
+    }
+}
+
+/// Resolves `S` from `Scope<C>` for the derives, through its impl for the scope or else from the
+/// scope first and the parent context otherwise
+///
+/// Picked through autoref like [`Resolver`](super::Resolver), so plain fields of a service derived
+/// for a scope see the values inserted in it
+pub struct ScopeResolver<S, C>(PhantomData<fn(&C) -> S>);
+
+impl<S, C> ScopeResolver<S, C> {
+    pub fn new() -> Self {
+        ScopeResolver(PhantomData)
+    }
+}
+
+impl<S, C> Default for ScopeResolver<S, C> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// [`ScopeResolver`] of services with a [`FromContext`] impl for the scope
+pub trait ScopeResolveDirect<S, C> {
+    fn resolve(&self, scope: &Scope<C>) -> S;
+
+    fn dependencies(&self, graph: &mut DependencyGraph);
+}
+
+impl<S: FromContext<Scope<C>>, C> ScopeResolveDirect<S, C> for ScopeResolver<S, C> {
+    #[inline]
+    fn resolve(&self, scope: &Scope<C>) -> S {
+        S::from_context(scope)
+    }
+
+    fn dependencies(&self, graph: &mut DependencyGraph) {
+        S::dependencies(graph)
+    }
+}
+
+/// [`ScopeResolver`] of services of the parent context, a clone of the one in the scope winning
+pub trait ScopeResolveFallback<S, C> {
+    fn resolve(&self, scope: &Scope<C>) -> S;
+
+    fn dependencies(&self, graph: &mut DependencyGraph);
+}
+
+impl<S, C> ScopeResolveFallback<S, C> for &ScopeResolver<S, C>
+where
+    S: FromContext<C> + Clone + Send + Sync + 'static,
+{
+    fn resolve(&self, scope: &Scope<C>) -> S {
+        match scope.get::<S>() {
+            Some(value) => S::clone(&value),
+            None => S::from_context(&scope.parent),
+        }
+    }
+
+    fn dependencies(&self, graph: &mut DependencyGraph) {
+        <S as FromContext<C>>::dependencies(graph)
+    }
+}
@@ -0,0 +1,171 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    ops::Deref,
+    sync::{Arc, Mutex, OnceLock},
+};
+
+use super::{DependencyGraph, FromContext, ResolveGuard};
+
+type Cell = Arc<OnceLock<Arc<dyn Any + Send + Sync>>>;
+
+/// Cache of the [`Singleton`]s built from a context, clones share the same cache
+#[derive(Clone, Default)]
+pub struct Singletons(Arc<Mutex<HashMap<TypeId, Cell>>>);
+
+impl Singletons {
+    fn get_or_init<T: Send + Sync + 'static>(&self, init: impl FnOnce() -> T) -> Arc<T> {
+        // Built outside the lock, so singletons can depend on other singletons
+        let cell = self
+            .0
+            .lock()
+            .unwrap()
+            .entry(TypeId::of::<T>())
+            .or_default()
+            .clone();
+
+        cell.get_or_init(|| Arc::new(init()))
+            .clone()
+            .downcast::<T>()
+            .expect("singletons are stored by their type id")
+    }
+}
+
+/// `T` built once per context and shared afterwards, the context must provide [`Singletons`].
+///
+/// Services are transient by default, built again on every resolution, and
+/// [`Scoped`](super::Scoped) ones are built once per dispatch
+pub struct Singleton<T>(Arc<T>);
+
+impl<T> Clone for Singleton<T> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<T> Deref for Singleton<T> {
+    type Target = T;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+impl<C, T> FromContext<C> for Singleton<T>
+where
+    Singletons: FromContext<C>,
+    T: FromContext<C> + Send + Sync + 'static,
+{
+    fn from_context(ctx: &C) -> Self {
+        // Entered before the cell, which would block on a singleton depending on itself
+        let _guard = ResolveGuard::enter::<Self>();
+        let singletons = Singletons::from_context(ctx);
+        Singleton(singletons.get_or_init(|| T::from_context(ctx)))
+    }
+
+    fn dependencies(graph: &mut DependencyGraph) {
+        if graph.visit::<Self>() {
+            graph.depends::<Self, T>();
+            T::dependencies(graph);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::{
+            atomic::{AtomicUsize, Ordering},
+            mpsc,
+        },
+        time::Duration,
+    };
+
+    use crate::context::{
+        Context, DependencyGraph, FromContext, Scope, Scoped, Singleton, Singletons,
+    };
+
+    static BUILT: AtomicUsize = AtomicUsize::new(0);
+
+    #[derive(Clone)]
+    struct Ctx {
+        singletons: Singletons,
+    }
+
+    impl Context for Ctx {}
+
+    impl FromContext<Ctx> for Singletons {
+        fn from_context(ctx: &Ctx) -> Self {
+            ctx.singletons.clone()
+        }
+    }
+
+    #[derive(Clone)]
+    struct Expensive;
+
+    impl FromContext<Ctx> for Expensive {
+        fn from_context(_: &Ctx) -> Self {
+            BUILT.fetch_add(1, Ordering::SeqCst);
+            Expensive
+        }
+    }
+
+    #[test]
+    fn caches_per_lifetime() {
+        let ctx = Ctx {
+            singletons: Singletons::default(),
+        };
+
+        let _: Singleton<Expensive> = ctx.provide();
+        let _: Singleton<Expensive> = ctx.clone().provide();
+        assert_eq!(BUILT.load(Ordering::SeqCst), 1);
+
+        let scope = Scope::new(ctx.clone());
+        let _: Scoped<Expensive> = scope.provide();
+        let _: Scoped<Expensive> = scope.provide();
+        assert_eq!(BUILT.load(Ordering::SeqCst), 2);
+
+        let _: Expensive = ctx.provide();
+        assert_eq!(BUILT.load(Ordering::SeqCst), 3);
+    }
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct Chicken(#[allow(dead_code)] Singleton<Egg>);
+
+    #[derive(FromContext)]
+    #[context(Ctx)]
+    struct Egg(#[allow(dead_code)] Singleton<Chicken>);
+
+    #[test]
+    fn declares_cycles_through_singletons() {
+        let graph = DependencyGraph::of::<Ctx, Singleton<Chicken>>();
+        assert!(graph.find_cycle().is_some());
+    }
+
+    #[test]
+    fn detects_cycles_between_singletons() {
+        let ctx = Ctx {
+            singletons: Singletons::default(),
+        };
+
+        let (sender, receiver) = mpsc::channel();
+        std::thread::spawn(move || {
+            let resolved = std::panic::catch_unwind(|| {
+                let _: Singleton<Chicken> = ctx.provide();
+            });
+            let _ = sender.send(resolved);
+        });
+
+        let payload = receiver
+            .recv_timeout(Duration::from_secs(5))
+            .expect("resolving the singletons deadlocked")
+            .expect_err("the cycle should have been detected");
+        let message = payload.downcast::<String>().unwrap();
+        assert!(
+            message.starts_with("dependency cycle detected"),
+            "{}",
+            message
+        );
+    }
+}
@@ -0,0 +1,306 @@
+use std::{
+    any::{Any, TypeId},
+    collections::HashMap,
+    future::Future,
+    ops::Deref,
+    sync::Arc,
+};
+
+use super::Context;
+
+type Factory = Arc<dyn Fn() -> Box<dyn Any> + Send + Sync>;
+
+type Overrides = Arc<HashMap<TypeId, Factory>>;
+
+tokio::task_local! {
+    /// The overrides of the `TestContext` resolving a service
+    static OVERRIDES: Overrides;
+}
+
+/// Wraps a context to replace some of its services in tests, like a repository with a fake
+///
+/// With the `test-context` feature, the derives implement their trait for `TestContext<C>` too,
+/// resolving like the impl for `C` with the overrides in effect. Every derived field then takes
+/// its override if there is one, so they also reach services resolved through hand-written impls
+/// or sub-contexts. Hand-written services get the impl from [`test_provide!`](crate::test_provide).
+/// Clones share the overrides until one of them adds its own
+#[derive(Clone)]
+pub struct TestContext<C> {
+    ctx: C,
+    overrides: Overrides,
+}
+
+impl<C> TestContext<C> {
+    pub fn new(ctx: C) -> Self {
+        Self {
+            ctx,
+            overrides: Arc::default(),
+        }
+    }
+
+    /// Every later resolution of `T` through this context gets a clone of `value`
+    pub fn with_override<T: Clone + Send + Sync + 'static>(mut self, value: T) -> Self {
+        let factory: Factory = Arc::new(move || Box::new(value.clone()));
+        Arc::make_mut(&mut self.overrides).insert(TypeId::of::<T>(), factory);
+        self
+    }
+
+    pub fn inner(&self) -> &C {
+        &self.ctx
+    }
+
+    /// The override of `T`, if it has one
+    pub fn get<T: 'static>(&self) -> Option<T> {
+        get(&self.overrides)
+    }
+
+    /// Resolves with `resolve` from the wrapped context, the overrides in effect meanwhile
+    pub fn resolve_with<T>(&self, resolve: impl FnOnce(&C) -> T) -> T {
+        OVERRIDES.sync_scope(self.overrides.clone(), || resolve(&self.ctx))
+    }
+
+    /// Like [`resolve_with`](Self::resolve_with), the overrides in effect whenever the future
+    /// is polled
+    pub fn resolve_async<'a, F>(
+        &'a self,
+        resolve: impl FnOnce(&'a C) -> F,
+    ) -> impl Future<Output = F::Output> + Send + 'a
+    where
+        F: Future + Send + 'a,
+    {
+        OVERRIDES.scope(self.overrides.clone(), resolve(&self.ctx))
+    }
+}
+
+impl<C> Deref for TestContext<C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        &self.ctx
+    }
+}
+
+impl<C: Context> Context for TestContext<C> {}
+
+/// The override of `T` in effect, while a [`TestContext`] resolves a service
+///
+/// The derives take it first for their fields
+pub fn overridden<T: 'static>() -> Option<T> {
+    OVERRIDES.try_with(get).ok().flatten()
+}
+
+fn get<T: 'static>(overrides: &Overrides) -> Option<T> {
+    let value = overrides.get(&TypeId::of::<T>())?()
+        .downcast::<T>()
+        .expect("overrides are stored by their type id");
+    Some(*value)
+}
+
+mod helpers {
+    /// Implements [`FromContext`](crate::context::FromContext) for the
+    /// [`TestContext`](crate::context::TestContext) of `ctx` on services with a hand-written impl
+    /// for it, an override of the service winning
+    #[macro_export]
+    macro_rules! test_provide (($ctx: path { $($service: path),* $(,)? }) => {
+        $(
+        impl $crate::context::FromContext<$crate::context::TestContext<$ctx>> for $service {
+            fn from_context(ctx: &$crate::context::TestContext<$ctx>) -> Self {
+                match ctx.get::<Self>() {
+                    Some(value) => value,
+                    None => ctx.resolve_with(<Self as $crate::context::FromContext<$ctx>>::from_context),
+                }
+            }
+
+            fn dependencies(graph: &mut $crate::context::DependencyGraph) {
+                <Self as $crate::context::FromContext<$ctx>>::dependencies(graph)
+            }
+        }
+        )*
+    });
+
+    pub use test_provide;
+}
+
+pub use helpers::*;
+
+#[cfg(test)]
+mod tests {
+    use std::sync::Arc;
+
+    use crate::{
+        context::{AsyncFromContext, Context, FromContext, TestContext, TryFromContext},
+        events::{router::Router, DomainEvent, Error, Handler},
+    };
+
+    trait Repo: Send + Sync {
+        fn name(&self) -> &'static str;
+    }
+
+    struct Postgres;
+    impl Repo for Postgres {
+        fn name(&self) -> &'static str {
+            "postgres"
+        }
+    }
+
+    struct Fake;
+    impl Repo for Fake {
+        fn name(&self) -> &'static str {
+            "fake"
+        }
+    }
+
+    #[derive(Clone)]
+    struct Repository(Arc<dyn Repo>);
+
+    #[derive(Clone, Default, Context)]
+    struct StoreContext;
+
+    #[derive(Clone, Default, Context)]
+    struct AppContext(#[extend] StoreContext);
+
+    impl FromContext<AppContext> for Repository {
+        fn from_context(_: &AppContext) -> Self {
+            Repository(Arc::new(Postgres))
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct Service {
+        repo: Repository,
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct Api {
+        service: Service,
+    }
+
+    #[derive(TryFromContext)]
+    #[context(AppContext)]
+    struct TryService {
+        repo: Repository,
+    }
+
+    impl FromContext<StoreContext> for Repository {
+        fn from_context(_: &StoreContext) -> Self {
+            Repository(Arc::new(Postgres))
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(StoreContext)]
+    struct Store {
+        repo: Repository,
+    }
+
+    crate::pub_export!(StoreContext { Store });
+
+    struct Audit {
+        service: Service,
+        store: Store,
+    }
+
+    impl FromContext<AppContext> for Audit {
+        fn from_context(ctx: &AppContext) -> Self {
+            Audit {
+                service: ctx.provide(),
+                store: ctx.provide_exported(),
+            }
+        }
+    }
+
+    crate::test_provide!(AppContext { Audit });
+
+    #[test]
+    fn resolves_overrides_first() {
+        let ctx = TestContext::new(AppContext::default());
+        let service: Service = ctx.provide();
+        assert_eq!(service.repo.0.name(), "postgres");
+
+        let faked = ctx.clone().with_override(Repository(Arc::new(Fake)));
+        let service: Service = faked.provide();
+        assert_eq!(service.repo.0.name(), "fake");
+
+        // Nested services see the overrides too
+        let api: Api = faked.provide();
+        assert_eq!(api.service.repo.0.name(), "fake");
+
+        // Neither the original context nor the wrapped one are changed
+        let service: Service = ctx.provide();
+        assert_eq!(service.repo.0.name(), "postgres");
+        let service: Service = faked.inner().provide();
+        assert_eq!(service.repo.0.name(), "postgres");
+
+        let service: TryService = faked.try_provide().unwrap();
+        assert_eq!(service.repo.0.name(), "fake");
+    }
+
+    #[test]
+    fn overrides_through_hand_written_and_exported_services() {
+        let ctx = TestContext::new(AppContext::default());
+        let faked = ctx.clone().with_override(Repository(Arc::new(Fake)));
+
+        let audit: Audit = faked.provide();
+        assert_eq!(audit.service.repo.0.name(), "fake");
+        assert_eq!(audit.store.repo.0.name(), "fake");
+
+        // The overrides are only in effect while resolving through the `TestContext`
+        let audit: Audit = faked.inner().provide();
+        assert_eq!(audit.store.repo.0.name(), "postgres");
+    }
+
+    #[derive(Clone)]
+    struct Ping;
+    impl DomainEvent for Ping {
+        fn name(&self) -> &'static str {
+            "Ping"
+        }
+
+        fn version(&self) -> &'static str {
+            "1"
+        }
+    }
+
+    #[derive(FromContext)]
+    #[context(AppContext)]
+    struct PingHandler {
+        repo: Repository,
+    }
+
+    impl Handler for PingHandler {
+        type Event = Ping;
+
+        async fn handle(&self, _: Ping) -> Result<(), Error> {
+            assert_eq!(self.repo.0.name(), "fake");
+            Ok(())
+        }
+    }
+
+    #[derive(AsyncFromContext)]
+    #[context(AppContext)]
+    struct AsyncPingHandler {
+        audit: Audit,
+    }
+
+    impl Handler for AsyncPingHandler {
+        type Event = Ping;
+
+        async fn handle(&self, _: Ping) -> Result<(), Error> {
+            assert_eq!(self.audit.store.repo.0.name(), "fake");
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn overrides_handler_dependencies() {
+        let mut router = Router::<TestContext<AppContext>>::default();
+        router.set_fatal_panics(true);
+        router.add::<PingHandler>();
+        router.add_async::<AsyncPingHandler>();
+
+        let ctx = TestContext::new(AppContext::default()).with_override(Repository(Arc::new(Fake)));
+        router.call(&ctx, Box::new(Ping)).unwrap().await;
+    }
+}
@@ -1,35 +1,176 @@
-use crate::{events::DomainEvent, tasks::Tasks};
+mod metrics;
+
+pub use metrics::BusMetrics;
+pub(crate) use metrics::Dispatching;
+
+use std::sync::Arc;
+
+use crate::{
+    events::{CorrelationId, DomainEvent},
+    router_bus::EventObserver,
+    tasks::{ShutdownSignal, Tasks},
+};
 
 #[derive(Clone)]
 pub struct EventBusPort {
-    tx: tokio::sync::mpsc::Sender<Box<dyn DomainEvent>>,
+    tx: tokio::sync::mpsc::Sender<(Arc<dyn DomainEvent>, CorrelationId)>,
     tasks: Tasks,
+    metrics: BusMetrics,
+    signal: ShutdownSignal,
+    on_publish: Option<EventObserver>,
 }
 
 impl EventBusPort {
+    /// Events published once the shutdown started are dropped
+    ///
+    /// Published by a handler, the event gets the [`CorrelationId`] of the one it handles
     pub fn publish(&self, event: impl DomainEvent + 'static) {
-        let event = Box::new(event);
+        self.publish_boxed(Box::new(event));
+    }
+
+    /// Same as [`publish`](Self::publish), for events whose type is not known
+    pub fn publish_boxed(&self, event: Box<dyn DomainEvent>) {
+        let event: Arc<dyn DomainEvent> = event.into();
+        let correlation = CorrelationId::current_or_new();
+        if let Some(on_publish) = &self.on_publish {
+            on_publish(event.clone());
+        }
 
         let tx = self.tx.clone();
+        let metrics = self.metrics.clone();
+        let signal = self.signal.clone();
+
+        // Queued before checking, so a draining socket always waits for it
+        metrics.event_queued();
+        if signal.is_stopping() {
+            metrics.event_dequeued();
+            signal.record_dropped();
+            return;
+        }
+
         self.tasks.spawn(async move {
-            let Err(e) = tx.send(event).await else {
+            let Err(e) = tx.send((event, correlation)).await else {
                 return;
             };
 
+            // The listener stopped, the event never reaches it
+            metrics.event_dequeued();
+            signal.record_dropped();
             eprintln!("Failed to send event: {}", e);
         });
     }
+
+    pub fn metrics(&self) -> &BusMetrics {
+        &self.metrics
+    }
 }
 
-pub struct EventBusSocket(tokio::sync::mpsc::Receiver<Box<dyn DomainEvent>>);
+pub struct EventBusSocket {
+    rx: tokio::sync::mpsc::Receiver<(Arc<dyn DomainEvent>, CorrelationId)>,
+    metrics: BusMetrics,
+    signal: ShutdownSignal,
+}
 
 impl EventBusSocket {
-    pub async fn recv(&mut self) -> Option<Box<dyn DomainEvent>> {
-        self.0.recv().await
+    /// Once the shutdown started, returns `None` as soon as every queued event is received
+    pub async fn recv(&mut self) -> Option<Arc<dyn DomainEvent>> {
+        self.recv_dispatching().await.map(|(event, _, _)| event)
+    }
+
+    /// Keeps the bus busy while the event is dispatched, until the guard is dropped
+    pub(crate) async fn recv_dispatching(
+        &mut self,
+    ) -> Option<(Arc<dyn DomainEvent>, CorrelationId, Dispatching)> {
+        loop {
+            // A publish counted right as the stop began drops its event without sending it,
+            // enabled before checking so that still wakes us
+            let dequeued = self.metrics.dequeued();
+            tokio::pin!(dequeued);
+            dequeued.as_mut().enable();
+
+            let stopping = self.signal.is_stopping();
+            if stopping && self.metrics.queued_events() == 0 {
+                return None;
+            }
+
+            tokio::select! {
+                event = self.rx.recv() => {
+                    let (event, correlation) = event?;
+                    return Some((event, correlation, self.metrics.dispatch()));
+                }
+                _ = self.signal.stopping(), if !stopping => {}
+                _ = dequeued, if stopping => {}
+            }
+        }
+    }
+
+    pub(crate) fn signal(&self) -> &ShutdownSignal {
+        &self.signal
+    }
+
+    pub fn metrics(&self) -> &BusMetrics {
+        &self.metrics
+    }
+}
+
+/// The events still queued once nothing listens anymore are dropped
+impl Drop for EventBusSocket {
+    fn drop(&mut self) {
+        self.rx.close();
+        while self.rx.try_recv().is_ok() {
+            self.metrics.event_dequeued();
+            self.signal.record_dropped();
+        }
     }
 }
 
-pub(crate) fn create(size: usize, tasks: Tasks) -> (EventBusPort, EventBusSocket) {
+pub(crate) fn create(
+    size: usize,
+    tasks: Tasks,
+    signal: ShutdownSignal,
+    on_publish: Option<EventObserver>,
+) -> (EventBusPort, EventBusSocket) {
     let (tx, rx) = tokio::sync::mpsc::channel(size);
-    (EventBusPort { tasks, tx }, EventBusSocket(rx))
+    let metrics = BusMetrics::default();
+
+    let port = EventBusPort {
+        tasks,
+        tx,
+        metrics: metrics.clone(),
+        signal: signal.clone(),
+        on_publish,
+    };
+
+    let socket = EventBusSocket {
+        rx,
+        metrics,
+        signal,
+    };
+
+    (port, socket)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn drains_publishes_racing_the_stop() {
+        let signal = ShutdownSignal::default();
+        let (port, mut socket) = create(10, Tasks::new(), signal.clone(), None);
+
+        // A publish counted its event, then saw the stop and dropped it
+        port.metrics().event_queued();
+        signal.stop();
+        let received = tokio::spawn(async move { socket.recv().await.is_none() });
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        port.metrics().event_dequeued();
+
+        let drained = tokio::time::timeout(Duration::from_secs(1), received)
+            .await
+            .expect("the socket should see the bus drained");
+        assert!(drained.unwrap());
+    }
 }
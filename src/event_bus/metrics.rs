@@ -0,0 +1,187 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use tokio::sync::{futures::Notified, Notify};
+
+/// Counters shared between the [`EventBusPort`](super::EventBusPort) and the router listening
+/// on the other side of the bus
+#[derive(Clone, Default)]
+pub struct BusMetrics(Arc<Counters>);
+
+/// Every work item moves to its next counter before leaving the previous one, so `busy` only
+/// reaches zero once nothing is left
+#[derive(Default)]
+struct Counters {
+    queued: AtomicUsize,
+    dispatching: AtomicUsize,
+    waiting: AtomicUsize,
+    running: AtomicUsize,
+    busy: AtomicUsize,
+    idle: Notify,
+    dequeued: Notify,
+}
+
+impl Counters {
+    fn enter(&self, counter: &AtomicUsize) {
+        self.busy.fetch_add(1, Ordering::SeqCst);
+        counter.fetch_add(1, Ordering::SeqCst);
+    }
+
+    fn leave(&self, counter: &AtomicUsize) {
+        counter.fetch_sub(1, Ordering::SeqCst);
+        if self.busy.fetch_sub(1, Ordering::SeqCst) == 1 {
+            self.idle.notify_waiters();
+        }
+    }
+}
+
+impl BusMetrics {
+    /// Events published but not yet picked up by the router
+    pub fn queued_events(&self) -> usize {
+        self.0.queued.load(Ordering::SeqCst)
+    }
+
+    /// Handler calls waiting for a concurrency permit
+    pub fn waiting_dispatches(&self) -> usize {
+        self.0.waiting.load(Ordering::SeqCst)
+    }
+
+    /// Handler calls currently running
+    pub fn running_handlers(&self) -> usize {
+        self.0.running.load(Ordering::SeqCst)
+    }
+
+    /// Everything accepted by the bus that has not started running yet
+    pub fn queue_depth(&self) -> usize {
+        self.queued_events() + self.waiting_dispatches()
+    }
+
+    /// No event queued or being dispatched, and no handler call waiting or running
+    pub fn is_idle(&self) -> bool {
+        self.0.busy.load(Ordering::SeqCst) == 0
+    }
+
+    /// Waits until the bus is idle, including whatever the handlers published in the meantime
+    ///
+    /// Idle is checked again after giving other tasks a turn, so work started right as the bus
+    /// went idle is waited for too. Tasks spawned by handlers outside of the bus are not seen
+    pub async fn idle(&self) {
+        loop {
+            let notified = self.0.idle.notified();
+            tokio::pin!(notified);
+            // Registered before checking, so going idle in between still wakes us
+            notified.as_mut().enable();
+
+            if self.is_idle() {
+                tokio::task::yield_now().await;
+                if self.is_idle() {
+                    return;
+                }
+                continue;
+            }
+
+            notified.await;
+        }
+    }
+
+    pub(crate) fn event_queued(&self) {
+        self.0.enter(&self.0.queued);
+    }
+
+    pub(crate) fn event_dequeued(&self) {
+        self.0.leave(&self.0.queued);
+        self.0.dequeued.notify_waiters();
+    }
+
+    /// Notified by the next [`event_dequeued`](Self::event_dequeued), once enabled
+    pub(crate) fn dequeued(&self) -> Notified<'_> {
+        self.0.dequeued.notified()
+    }
+
+    /// Moves a queued event to being dispatched, until its handler calls wait for permits
+    pub(crate) fn dispatch(&self) -> Dispatching {
+        self.0.enter(&self.0.dispatching);
+        self.event_dequeued();
+        Dispatching(self.0.clone())
+    }
+
+    pub(crate) fn wait(&self) -> Waiting {
+        self.0.enter(&self.0.waiting);
+        Waiting(self.0.clone())
+    }
+}
+
+/// An event taken from the bus whose handler calls are not all spawned yet
+pub(crate) struct Dispatching(Arc<Counters>);
+
+impl Drop for Dispatching {
+    fn drop(&mut self) {
+        self.0.leave(&self.0.dispatching);
+    }
+}
+
+/// A dispatch waiting for its permits, turns into [`Running`] once it gets them
+pub(crate) struct Waiting(Arc<Counters>);
+
+impl Waiting {
+    pub(crate) fn run(self) -> Running {
+        let counters = self.0.clone();
+        counters.enter(&counters.running);
+        drop(self);
+
+        Running(counters)
+    }
+}
+
+impl Drop for Waiting {
+    fn drop(&mut self) {
+        self.0.leave(&self.0.waiting);
+    }
+}
+
+pub(crate) struct Running(Arc<Counters>);
+
+impl Drop for Running {
+    fn drop(&mut self) {
+        self.0.leave(&self.0.running);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time::Duration;
+
+    use super::*;
+
+    #[tokio::test]
+    async fn waits_for_every_counter() {
+        let metrics = BusMetrics::default();
+        assert!(metrics.is_idle());
+        metrics.idle().await;
+
+        metrics.event_queued();
+        let dispatching = metrics.dispatch();
+        assert_eq!(metrics.queued_events(), 0);
+        assert!(!metrics.is_idle());
+
+        let waiting = metrics.wait();
+        drop(dispatching);
+
+        let idle = tokio::spawn({
+            let metrics = metrics.clone();
+            async move { metrics.idle().await }
+        });
+
+        let running = waiting.run();
+        tokio::time::sleep(Duration::from_millis(10)).await;
+        assert!(!idle.is_finished());
+
+        drop(running);
+        tokio::time::timeout(Duration::from_secs(1), idle)
+            .await
+            .expect("bus should be idle")
+            .unwrap();
+    }
+}
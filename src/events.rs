@@ -1,13 +1,63 @@
 pub use cream_events_core::DomainEvent;
 
-use std::future::Future;
+use std::{borrow::Cow, fmt, future::Future, time::Duration};
 
-pub enum Error {}
+#[derive(Debug)]
+pub enum Error {
+    /// The handler did not finish within its timeout
+    Timeout(Duration),
+    /// The handler panicked, with the panic message
+    Panic(String),
+    /// The handler could not be built from the context
+    Resolve(Box<dyn std::error::Error + Send + Sync>),
+    Other(Box<dyn std::error::Error + Send + Sync>),
+}
+
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Timeout(timeout) => write!(f, "timed out after {:?}", timeout),
+            Self::Panic(msg) => write!(f, "panicked: {}", msg),
+            Self::Resolve(err) => write!(f, "could not be resolved: {}", err),
+            Self::Other(err) => write!(f, "{}", err),
+        }
+    }
+}
+
+impl std::error::Error for Error {}
+
+/// A handler call, or a run of a [`Supervisor`](crate::tasks::Supervisor) service, that ended
+/// in an error
+#[derive(Debug)]
+pub struct Failure {
+    /// The handler, or the service
+    pub handler: Cow<'static, str>,
+    /// The event handled, none for a service
+    pub event: Option<&'static str>,
+    pub error: Error,
+}
+
+impl fmt::Display for Failure {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.event {
+            Some(event) => write!(
+                f,
+                "{} failed handling {}: {}",
+                self.handler, event, self.error
+            ),
+            None => write!(f, "service {} failed: {}", self.handler, self.error),
+        }
+    }
+}
 
 pub trait Handler: Send {
     type Event: DomainEvent + Sized + Send + 'static + Clone;
     fn handle(&self, event: Self::Event) -> impl Future<Output = Result<(), Error>> + Send;
 }
 
+mod blocking;
+mod correlation;
 pub mod router;
 
+pub use blocking::*;
+pub use correlation::*;
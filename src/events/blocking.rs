@@ -0,0 +1,173 @@
+use std::sync::Arc;
+
+use crate::{
+    context::{DependencyGraph, FromContext},
+    events::{router::catch_panic::panic_message, DomainEvent, Error, Handler},
+};
+
+/// A handler doing heavy synchronous work, like rendering or hashing
+///
+/// Added to the router as [`Blocking<H>`], so it runs on the blocking thread pool instead of
+/// stalling the async workers
+pub trait BlockingHandler: Send + Sync + 'static {
+    type Event: DomainEvent + Sized + Send + 'static + Clone;
+    fn handle(&self, event: Self::Event) -> Result<(), Error>;
+}
+
+/// Runs `H` with `spawn_blocking`, the handler call awaits it so [`Tasks`](crate::tasks::Tasks)
+/// and the shutdown wait for it too
+///
+/// Blocking work can not be interrupted, a timeout or an abort only stops waiting for it
+pub struct Blocking<H>(Arc<H>);
+
+impl<C, H: BlockingHandler + FromContext<C>> FromContext<C> for Blocking<H> {
+    fn from_context(ctx: &C) -> Self {
+        Blocking(Arc::new(H::from_context(ctx)))
+    }
+
+    fn dependencies(graph: &mut DependencyGraph) {
+        if graph.visit::<Self>() {
+            graph.depends::<Self, H>();
+            H::dependencies(graph);
+        }
+    }
+}
+
+impl<H: BlockingHandler> Handler for Blocking<H> {
+    type Event = H::Event;
+
+    async fn handle(&self, event: Self::Event) -> Result<(), Error> {
+        let handler = self.0.clone();
+        match tokio::task::spawn_blocking(move || handler.handle(event)).await {
+            Ok(result) => result,
+            Err(err) if err.is_panic() => Err(Error::Panic(panic_message(err.into_panic()))),
+            Err(err) => Err(Error::Other(err.into())),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        sync::atomic::{AtomicBool, AtomicUsize, Ordering},
+        time::Duration,
+    };
+
+    use crate::{
+        context::{events_context::EventsContextBuilder, Context, CreamContext},
+        event_bus::EventBusPort,
+        events::{router::Router, CorrelationId},
+        tasks::Shutdown,
+    };
+
+    use super::*;
+
+    #[derive(Clone)]
+    struct Ctx {
+        rendered: Arc<AtomicBool>,
+    }
+
+    impl Context for Ctx {}
+
+    #[derive(Clone)]
+    struct Render(&'static str);
+    impl DomainEvent for Render {
+        fn name(&self) -> &'static str {
+            "Render"
+        }
+
+        fn version(&self) -> &'static str {
+            "1.0.0"
+        }
+    }
+
+    struct Renderer {
+        rendered: Arc<AtomicBool>,
+    }
+
+    impl FromContext<Ctx> for Renderer {
+        fn from_context(ctx: &Ctx) -> Self {
+            Renderer {
+                rendered: ctx.rendered.clone(),
+            }
+        }
+    }
+
+    impl BlockingHandler for Renderer {
+        type Event = Render;
+        fn handle(&self, Render(doc): Render) -> Result<(), Error> {
+            if doc == "broken" {
+                panic!("could not render");
+            }
+
+            std::thread::sleep(Duration::from_millis(50));
+            self.rendered.store(true, Ordering::SeqCst);
+            Ok(())
+        }
+    }
+
+    fn ctx() -> Ctx {
+        Ctx {
+            rendered: Arc::default(),
+        }
+    }
+
+    #[tokio::test(flavor = "current_thread")]
+    async fn runs_off_the_async_workers() {
+        let ctx = ctx();
+        let mut router = Router::<Ctx>::default();
+        router.add::<Blocking<Renderer>>();
+
+        let ticks = Arc::new(AtomicUsize::new(0));
+        let ticker = tokio::spawn({
+            let ticks = ticks.clone();
+            async move {
+                loop {
+                    tokio::time::sleep(Duration::from_millis(5)).await;
+                    ticks.fetch_add(1, Ordering::SeqCst);
+                }
+            }
+        });
+
+        router.call(&ctx, Box::new(Render("report"))).unwrap().await;
+        ticker.abort();
+
+        assert!(ctx.rendered.load(Ordering::SeqCst));
+        assert!(ticks.load(Ordering::SeqCst) > 0, "the worker was blocked");
+
+        let dispatch = router
+            .dispatch(
+                &Arc::new(ctx.clone()),
+                &Render("broken"),
+                CorrelationId::new(),
+            )
+            .unwrap()
+            .pop()
+            .unwrap();
+        assert!(matches!(dispatch.fut.await, Err(Error::Panic(msg)) if msg == "could not render"));
+    }
+
+    #[tokio::test]
+    async fn shutdown_waits_for_blocking_handlers() {
+        let ctx = ctx();
+        let mut router = Router::<Ctx>::default();
+        router.add::<Blocking<Renderer>>();
+
+        let cream_ctx = CreamContext::default();
+        let (events_ctx, setup) = EventsContextBuilder::default().build(&cream_ctx);
+        setup.setup(router, ctx.clone());
+
+        let port: EventBusPort = events_ctx.provide();
+        port.publish(Render("report"));
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        assert!(shutdown.run().await.is_clean());
+        assert!(ctx.rendered.load(Ordering::SeqCst));
+    }
+
+    #[test]
+    fn declares_the_blocking_handler_dependencies() {
+        let graph = DependencyGraph::of::<Ctx, Blocking<Renderer>>();
+        assert_eq!(graph.nodes().count(), 2);
+    }
+}
@@ -0,0 +1,55 @@
+use std::{
+    fmt,
+    future::Future,
+    sync::atomic::{AtomicU64, Ordering},
+};
+
+tokio::task_local! {
+    static CURRENT: CorrelationId;
+}
+
+/// Ties together an event and every event published while handling it, in turn
+///
+/// Events published outside handlers start a new one. Only the task running the handler carries
+/// it, publishing from a task it spawns starts a new one too
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub struct CorrelationId(u64);
+
+impl CorrelationId {
+    /// Unique within the process
+    pub fn new() -> Self {
+        static NEXT: AtomicU64 = AtomicU64::new(1);
+        Self(NEXT.fetch_add(1, Ordering::Relaxed))
+    }
+
+    /// The id of the event handled by the current task, if any
+    pub fn current() -> Option<Self> {
+        CURRENT.try_with(|id| *id).ok()
+    }
+
+    /// The id events published now get
+    pub(crate) fn current_or_new() -> Self {
+        Self::current().unwrap_or_default()
+    }
+
+    /// Runs a handler call with this id as the current one
+    pub(crate) fn scope<F: Future>(self, fut: F) -> impl Future<Output = F::Output> {
+        CURRENT.scope(self, fut)
+    }
+
+    pub fn as_u64(&self) -> u64 {
+        self.0
+    }
+}
+
+impl Default for CorrelationId {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Display for CorrelationId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{:016x}", self.0)
+    }
+}
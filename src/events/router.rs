@@ -1,14 +1,31 @@
-use std::{any::TypeId, collections::HashMap, future::Future, pin::Pin};
+pub(crate) mod catch_panic;
 
-use tokio::task::JoinSet;
+use std::{
+    any::TypeId,
+    cell::OnceCell,
+    collections::HashMap,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc,
+    },
+    time::Duration,
+};
+
+use catch_panic::{panic_message, CatchPanic};
 
 use crate::{
-    context::ContextProvide,
-    events::{DomainEvent, Handler},
+    context::{AsyncFromContext, DependencyGraph, FromContext, Scope, TryFromContext},
+    events::{CorrelationId, DomainEvent, Error, Failure, Handler},
+    router_bus::{log_failure, FailureHandler},
+    tasks::Tasks,
 };
 
 trait Handlers<C>: AsAnyC<C> + Send {
-    fn call(&self, ctx: &C, event: Box<dyn DomainEvent>) -> JoinSet<()>;
+    fn call(&self, call: &Call<C>) -> Vec<Dispatch>;
+    fn dependencies(&self, graph: &mut DependencyGraph);
 }
 
 trait AsAnyC<C> {
@@ -24,37 +41,198 @@ where
     }
 }
 
-type Caller<C, E> = fn(&C, E) -> Pin<Box<dyn Future<Output = ()> + Send>>;
-struct EventHandlers<C, E>(Vec<Caller<C, E>>);
+type HandlerFuture = Pin<Box<dyn Future<Output = Result<(), Error>> + Send>>;
+type Caller<C, E> = fn(&Call<C>, E) -> HandlerFuture;
+type ScopeInit<C> = Box<dyn Fn(&Scope<C>, &dyn DomainEvent) + Send + Sync>;
+
+/// The event being dispatched, available to scoped handlers
+#[derive(Clone, Debug)]
+pub struct EventInfo {
+    pub name: &'static str,
+    pub version: &'static str,
+    /// Unique for every dispatch of the router
+    pub id: u64,
+    /// Shared with the event that caused this one, if a handler published it
+    pub correlation_id: CorrelationId,
+}
+
+impl<C> FromContext<Scope<C>> for EventInfo {
+    fn from_context(scope: &Scope<C>) -> Self {
+        let info = scope
+            .get::<EventInfo>()
+            .expect("EventInfo is set on every scope built by the router");
+
+        EventInfo::clone(&info)
+    }
+}
+
+/// What callers resolve their handlers from, the scope is only built if a handler needs it
+///
+/// Scoped handler calls share the scope, the last one to finish drops it
+struct Call<'a, C> {
+    ctx: &'a Arc<C>,
+    event: &'a dyn DomainEvent,
+    info: EventInfo,
+    init: Option<&'a ScopeInit<C>>,
+    scope: OnceCell<Arc<Scope<C>>>,
+}
+
+impl<C> Call<'_, C> {
+    fn scope(&self) -> &Arc<Scope<C>> {
+        self.scope.get_or_init(|| {
+            let scope = Scope::shared(Arc::clone(self.ctx));
+            scope.insert(self.info.clone());
+
+            if let Some(init) = self.init {
+                init(&scope, self.event);
+            }
+
+            Arc::new(scope)
+        })
+    }
+}
+
+/// Per handler settings, given when adding it to the [`Router`]
+#[derive(Clone, Default)]
+pub struct HandlerOptions {
+    timeout: Option<Duration>,
+}
+
+impl HandlerOptions {
+    /// Overrides the default handler timeout of the bus
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+}
+
+/// A single handler call for an event, not yet running
+pub(crate) struct Dispatch {
+    pub(crate) handler: TypeId,
+    pub(crate) handler_name: &'static str,
+    pub(crate) event_name: &'static str,
+    pub(crate) timeout: Option<Duration>,
+    pub(crate) fut: HandlerFuture,
+}
+
+struct Entry<C, E> {
+    id: TypeId,
+    name: &'static str,
+    options: HandlerOptions,
+    caller: Caller<C, E>,
+    dependencies: fn(&mut DependencyGraph),
+}
+
+struct EventHandlers<C, E>(Vec<Entry<C, E>>);
 
 impl<C: 'static, E: DomainEvent + Clone> Handlers<C> for EventHandlers<C, E> {
-    fn call(&self, ctx: &C, event: Box<dyn DomainEvent>) -> JoinSet<()> {
-        let event = event
+    fn call(&self, call: &Call<C>) -> Vec<Dispatch> {
+        let event = call
+            .event
             .as_any()
             .downcast_ref::<E>()
             .expect("Invalid event type");
 
         self.0
             .iter()
-            .map(|handler| (handler)(ctx, event.clone()))
+            .map(|entry| Dispatch {
+                handler: entry.id,
+                handler_name: entry.name,
+                event_name: event.name(),
+                timeout: entry.options.timeout,
+                fut: (entry.caller)(call, event.clone()),
+            })
             .collect()
     }
+
+    fn dependencies(&self, graph: &mut DependencyGraph) {
+        for entry in &self.0 {
+            (entry.dependencies)(graph);
+        }
+    }
 }
 
 impl<C, E: DomainEvent> EventHandlers<C, E> {
-    fn add<H>(&mut self)
-    where
-        H: Handler<Event = E> + Send + 'static,
-        C: ContextProvide<H>,
+    fn add<H>(
+        &mut self,
+        options: HandlerOptions,
+        caller: Caller<C, E>,
+        dependencies: fn(&mut DependencyGraph),
+    ) where
+        H: Handler<Event = E> + 'static,
     {
-        let caller: Caller<C, H::Event> = |ctx, event| {
-            let handler: H = ctx.provide();
-            Box::pin(async move {
-                let _ = handler.handle(event).await;
-            })
-        };
+        self.0.push(Entry {
+            id: TypeId::of::<H>(),
+            name: std::any::type_name::<H>(),
+            options,
+            caller,
+            dependencies,
+        });
+    }
+}
+
+fn resolve_caller<C, H>() -> Caller<C, H::Event>
+where
+    C: Send + Sync + 'static,
+    H: Handler + TryFromContext<C> + 'static,
+    H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    |call, event| {
+        let ctx = Arc::clone(call.ctx);
+        Box::pin(async move { handle_resolved(|| H::try_from_context(&ctx), event).await })
+    }
+}
+
+fn scoped_caller<C, H>() -> Caller<C, H::Event>
+where
+    C: Send + Sync + 'static,
+    H: Handler + TryFromContext<Scope<C>> + 'static,
+    H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    |call, event| {
+        let scope = Arc::clone(call.scope());
+        Box::pin(async move {
+            let result = handle_resolved(|| H::try_from_context(&scope), event).await;
+            drop(scope);
+            result
+        })
+    }
+}
+
+fn handle_resolved<H, Err>(
+    resolve: impl FnOnce() -> Result<H, Err>,
+    event: H::Event,
+) -> HandlerFuture
+where
+    H: Handler + 'static,
+    Err: Into<Box<dyn std::error::Error + Send + Sync>>,
+{
+    let handler = match catch_unwind(AssertUnwindSafe(resolve)) {
+        Ok(Ok(handler)) => handler,
+        Ok(Err(err)) => return Box::pin(std::future::ready(Err(Error::Resolve(err.into())))),
+        Err(payload) => {
+            let error = Error::Panic(panic_message(payload));
+            return Box::pin(std::future::ready(Err(error)));
+        }
+    };
+
+    let fut = Box::pin(async move { handler.handle(event).await });
+    Box::pin(CatchPanic(fut))
+}
+
+fn async_caller<C, H>() -> Caller<C, H::Event>
+where
+    C: Send + Sync + 'static,
+    H: Handler + AsyncFromContext<C> + 'static,
+{
+    |call, event| {
+        let ctx = Arc::clone(call.ctx);
+        let fut = Box::pin(async move {
+            let handler = H::from_context_async(&ctx).await;
+            handler.handle(event).await
+        });
 
-        self.0.push(caller);
+        Box::pin(CatchPanic(fut))
     }
 }
 
@@ -64,60 +242,248 @@ impl<C, E> Default for EventHandlers<C, E> {
     }
 }
 
-pub struct Router<C>(HashMap<TypeId, Box<dyn Handlers<C>>>);
+pub struct Router<C> {
+    handlers: HashMap<TypeId, Box<dyn Handlers<C>>>,
+    fatal_panics: bool,
+    scope_init: Option<ScopeInit<C>>,
+    next_dispatch: AtomicU64,
+    tasks: Tasks,
+    on_failure: FailureHandler,
+}
 
 impl<C> Default for Router<C> {
     fn default() -> Self {
-        Self(HashMap::new())
+        Self {
+            handlers: HashMap::new(),
+            fatal_panics: false,
+            scope_init: None,
+            next_dispatch: AtomicU64::new(0),
+            tasks: Tasks::new(),
+            on_failure: Arc::new(log_failure),
+        }
     }
 }
 
 impl<C: 'static> Router<C> {
-    pub fn call(&self, ctx: &C, event: Box<dyn DomainEvent>) -> Option<impl Future<Output = ()>> {
+    /// Handlers are only resolved once running, from a clone of the context shared by the calls
+    ///
+    /// Every call is a task of the router's [`Tasks`], their failures go to its failure handler
+    pub fn call(&self, ctx: &C, event: Box<dyn DomainEvent>) -> Option<impl Future<Output = ()>>
+    where
+        C: Clone,
+    {
+        let ctx = Arc::new(C::clone(ctx));
+        let handles: Vec<_> = self
+            .dispatch(&ctx, &*event, CorrelationId::current_or_new())?
+            .into_iter()
+            .map(|dispatch| {
+                let on_failure = self.on_failure.clone();
+                self.tasks.spawn_named(dispatch.handler_name, async move {
+                    let error = dispatch.fut.await.err()?;
+                    let panic = match &error {
+                        Error::Panic(msg) => Some(msg.clone()),
+                        _ => None,
+                    };
+
+                    on_failure(Failure {
+                        handler: dispatch.handler_name.into(),
+                        event: Some(dispatch.event_name),
+                        error,
+                    });
+                    panic
+                })
+            })
+            .collect();
+
+        let fatal_panics = self.fatal_panics;
+        Some(async move {
+            for handle in handles {
+                if let (true, Ok(Some(msg))) = (fatal_panics, handle.join().await) {
+                    panic!("handler panicked: {}", msg);
+                }
+            }
+        })
+    }
+
+    /// Where [`call`](Self::call) spawns the handlers, so a shutdown waits for them
+    pub fn set_tasks(&mut self, tasks: Tasks) {
+        self.tasks = tasks;
+    }
+
+    /// Receives the errors of the handlers run by [`call`](Self::call), logged by default
+    pub fn set_failure_handler(&mut self, on_failure: FailureHandler) {
+        self.on_failure = on_failure;
+    }
+
+    /// Handler panics are caught and reported as [`Error::Panic`], when fatal they are also
+    /// raised again by [`call`](Self::call), useful to make tests fail
+    ///
+    /// On the bus they are kept in [`ShutdownReport::handler_panics`](crate::tasks::ShutdownReport::handler_panics)
+    pub fn set_fatal_panics(&mut self, fatal: bool) {
+        self.fatal_panics = fatal;
+    }
+
+    pub fn fatal_panics(&self) -> bool {
+        self.fatal_panics
+    }
+
+    /// The calls of every handler for the event, leaving to the caller how to run them
+    ///
+    /// Handlers are resolved when their call is first polled, and run with `correlation` as the
+    /// current [`CorrelationId`]
+    pub(crate) fn dispatch(
+        &self,
+        ctx: &Arc<C>,
+        event: &dyn DomainEvent,
+        correlation: CorrelationId,
+    ) -> Option<Vec<Dispatch>> {
         let id = event.as_any().type_id();
+        let handlers = self.handlers.get(&id)?;
+
+        let call = Call {
+            ctx,
+            event,
+            info: EventInfo {
+                name: event.name(),
+                version: event.version(),
+                id: self.next_dispatch.fetch_add(1, Ordering::Relaxed),
+                correlation_id: correlation,
+            },
+            init: self.scope_init.as_ref(),
+            scope: OnceCell::new(),
+        };
+
+        let dispatches = handlers
+            .call(&call)
+            .into_iter()
+            .map(|dispatch| Dispatch {
+                fut: Box::pin(correlation.scope(dispatch.fut)),
+                ..dispatch
+            })
+            .collect();
+        Some(dispatches)
+    }
+
+    /// Every handler and what it is built from
+    pub fn dependency_graph(&self) -> DependencyGraph {
+        let mut graph = DependencyGraph::default();
+        for handlers in self.handlers.values() {
+            handlers.dependencies(&mut graph);
+        }
 
-        let handlers = self.0.get(&id)?;
-        let mut join = handlers.call(ctx, event);
-        Some(async move { while join.join_next().await.is_some() {} })
+        graph
     }
 
+    /// Fills the [`Scope`] built for every event before its scoped handlers are resolved
+    pub fn set_scope_init(
+        &mut self,
+        init: impl Fn(&Scope<C>, &dyn DomainEvent) + Send + Sync + 'static,
+    ) {
+        self.scope_init = Some(Box::new(init));
+    }
+
+    /// Resolution errors of `H` are reported as [`Error::Resolve`] when the event is handled
+    ///
+    /// `H` is bound by [`TryFromContext`] rather than `C: ContextProvide<H>` as before, every
+    /// [`FromContext`] handler implementing it too. Code naming the old bound in its own where
+    /// clauses moves to `H: TryFromContext<C>`. `C` is `Send + Sync` as handlers are resolved in
+    /// their own task, from the context the calls share
     pub fn add<H>(&mut self)
     where
-        H: Handler + 'static,
-        C: ContextProvide<H>,
+        C: Send + Sync,
+        H: Handler + TryFromContext<C> + 'static,
+        H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
     {
-        let id = TypeId::of::<H::Event>();
-        match self.0.get_mut(&id) {
-            None => {
-                let mut handlers = EventHandlers::<C, H::Event>::default();
-                handlers.add::<H>();
-                self.0.insert(id, Box::new(handlers));
-            }
+        self.add_with::<H>(HandlerOptions::default());
+    }
 
-            Some(handlers) => {
-                handlers
-                    .as_any_mut()
-                    .downcast_mut::<EventHandlers<C, H::Event>>()
-                    .expect("Invalid handler type")
-                    .add::<H>();
-            }
+    pub fn add_with<H>(&mut self, options: HandlerOptions)
+    where
+        C: Send + Sync,
+        H: Handler + TryFromContext<C> + 'static,
+        H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let dependencies = |graph: &mut DependencyGraph| {
+            graph.add::<C, H>();
+        };
+
+        self.event_handlers::<H::Event>()
+            .add::<H>(options, resolve_caller::<C, H>(), dependencies);
+    }
+
+    /// For handlers resolved from a [`Scope`] of `C` built for each event, kept until the last
+    /// of them is done with the event
+    pub fn add_scoped<H>(&mut self)
+    where
+        C: Send + Sync,
+        H: Handler + TryFromContext<Scope<C>> + 'static,
+        H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        self.add_scoped_with::<H>(HandlerOptions::default());
+    }
+
+    pub fn add_scoped_with<H>(&mut self, options: HandlerOptions)
+    where
+        C: Send + Sync,
+        H: Handler + TryFromContext<Scope<C>> + 'static,
+        H::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+    {
+        let dependencies = |graph: &mut DependencyGraph| {
+            graph.add::<Scope<C>, H>();
         };
+
+        self.event_handlers::<H::Event>()
+            .add::<H>(options, scoped_caller::<C, H>(), dependencies);
+    }
+
+    /// For handlers with dependencies built asynchronously, resolved right before handling
+    pub fn add_async<H>(&mut self)
+    where
+        C: Send + Sync,
+        H: Handler + AsyncFromContext<C> + 'static,
+    {
+        self.add_async_with::<H>(HandlerOptions::default());
+    }
+
+    pub fn add_async_with<H>(&mut self, options: HandlerOptions)
+    where
+        C: Send + Sync,
+        H: Handler + AsyncFromContext<C> + 'static,
+    {
+        // Async resolution does not declare its dependencies
+        let dependencies = |graph: &mut DependencyGraph| {
+            graph.visit::<H>();
+        };
+
+        self.event_handlers::<H::Event>()
+            .add::<H>(options, async_caller::<C, H>(), dependencies);
+    }
+
+    fn event_handlers<E: DomainEvent + Clone>(&mut self) -> &mut EventHandlers<C, E> {
+        self.handlers
+            .entry(TypeId::of::<E>())
+            .or_insert_with(|| Box::new(EventHandlers::<C, E>::default()))
+            .as_any_mut()
+            .downcast_mut::<EventHandlers<C, E>>()
+            .expect("Invalid handler type")
     }
 }
 
 #[cfg(test)]
 pub mod tests {
-    use std::sync::Mutex;
+    use std::sync::{Arc, Mutex};
 
     use crate::{
         context::{Context, FromContext},
-        events::{DomainEvent, Error, Handler},
+        events::{CorrelationId, DomainEvent, Error, Handler},
     };
 
     #[test]
     fn router_calls() {
+        // Handlers are resolved from a clone of the context
+        #[derive(Clone)]
         struct MockContext {
-            val: Mutex<bool>,
+            val: Arc<Mutex<bool>>,
         }
 
         impl MockContext {
@@ -161,7 +527,7 @@ pub mod tests {
         router.add::<TestHandler>();
 
         let context = MockContext {
-            val: Mutex::new(false),
+            val: Arc::default(),
         };
 
         let val = tokio::runtime::Builder::new_current_thread()
@@ -174,4 +540,311 @@ pub mod tests {
 
         assert!(val);
     }
+
+    #[derive(Clone)]
+    struct PanicContext;
+    impl Context for PanicContext {}
+
+    #[derive(Clone)]
+    struct PanicEvent;
+
+    impl DomainEvent for PanicEvent {
+        fn name(&self) -> &'static str {
+            "PanicEvent"
+        }
+
+        fn version(&self) -> &'static str {
+            "1.0.0"
+        }
+    }
+
+    static CALM_RAN: std::sync::atomic::AtomicBool = std::sync::atomic::AtomicBool::new(false);
+
+    struct PanickingHandler;
+    impl FromContext<PanicContext> for PanickingHandler {
+        fn from_context(_: &PanicContext) -> Self {
+            PanickingHandler
+        }
+    }
+
+    impl Handler for PanickingHandler {
+        type Event = PanicEvent;
+        async fn handle(&self, _event: Self::Event) -> Result<(), Error> {
+            panic!("boom");
+        }
+    }
+
+    struct CalmHandler;
+    impl FromContext<PanicContext> for CalmHandler {
+        fn from_context(_: &PanicContext) -> Self {
+            CalmHandler
+        }
+    }
+
+    impl Handler for CalmHandler {
+        type Event = PanicEvent;
+        async fn handle(&self, _event: Self::Event) -> Result<(), Error> {
+            CALM_RAN.store(true, std::sync::atomic::Ordering::Relaxed);
+            Ok(())
+        }
+    }
+
+    #[tokio::test]
+    async fn isolates_panics() {
+        let mut router = super::Router::<PanicContext>::default();
+        router.add::<PanickingHandler>();
+        router.add::<CalmHandler>();
+
+        let mut dispatches = router
+            .dispatch(&Arc::new(PanicContext), &PanicEvent, CorrelationId::new())
+            .unwrap();
+
+        let calm = dispatches.pop().unwrap();
+        let panicking = dispatches.pop().unwrap();
+
+        assert!(matches!(panicking.fut.await, Err(Error::Panic(msg)) if msg == "boom"));
+        assert!(calm.fut.await.is_ok());
+        assert!(CALM_RAN.load(std::sync::atomic::Ordering::Relaxed));
+    }
+
+    #[tokio::test]
+    #[should_panic(expected = "handler panicked: boom")]
+    async fn fatal_panics_propagate() {
+        let mut router = super::Router::<PanicContext>::default();
+        router.add::<PanickingHandler>();
+        router.set_fatal_panics(true);
+
+        router
+            .call(&PanicContext, Box::new(PanicEvent))
+            .unwrap()
+            .await;
+    }
+
+    #[tokio::test]
+    async fn reports_call_failures() {
+        let failures = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let tasks = crate::tasks::Tasks::new();
+
+        let mut router = super::Router::<PanicContext>::default();
+        router.add::<PanickingHandler>();
+        router.add::<CalmHandler>();
+        router.set_tasks(tasks.clone());
+        router.set_failure_handler({
+            let failures = failures.clone();
+            Arc::new(move |failure: super::Failure| {
+                failures
+                    .lock()
+                    .unwrap()
+                    .push((failure.handler, failure.error.to_string()))
+            })
+        });
+
+        let call = router.call(&PanicContext, Box::new(PanicEvent)).unwrap();
+        tasks.close();
+        tasks.wait().await;
+        call.await;
+
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(failures[0].0.ends_with("PanickingHandler"));
+        assert!(failures[0].1.contains("boom"));
+    }
+
+    #[tokio::test]
+    async fn reports_resolution_errors() {
+        use crate::context::TryFromContext;
+
+        struct Config;
+        impl TryFromContext<PanicContext> for Config {
+            type Error = String;
+            fn try_from_context(_: &PanicContext) -> Result<Self, Self::Error> {
+                Err("missing config".to_string())
+            }
+        }
+
+        #[derive(TryFromContext)]
+        #[context(PanicContext)]
+        struct ConfiguredHandler {
+            _config: Config,
+        }
+
+        impl Handler for ConfiguredHandler {
+            type Event = PanicEvent;
+            async fn handle(&self, _event: Self::Event) -> Result<(), Error> {
+                Ok(())
+            }
+        }
+
+        assert!(PanicContext.try_provide::<ConfiguredHandler>().is_err());
+
+        let mut router = super::Router::<PanicContext>::default();
+        router.add::<ConfiguredHandler>();
+
+        let dispatch = router
+            .dispatch(&Arc::new(PanicContext), &PanicEvent, CorrelationId::new())
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        match dispatch.fut.await {
+            Err(Error::Resolve(err)) => {
+                assert!(err.to_string().ends_with("`_config`: missing config"))
+            }
+            _ => panic!("expected a resolution error"),
+        }
+    }
+
+    #[tokio::test]
+    async fn resolves_async_handlers() {
+        use crate::context::AsyncFromContext;
+
+        struct Token(&'static str);
+        impl AsyncFromContext<PanicContext> for Token {
+            async fn from_context_async(_: &PanicContext) -> Self {
+                tokio::task::yield_now().await;
+                Token("fetched")
+            }
+        }
+
+        struct Region(&'static str);
+        impl FromContext<PanicContext> for Region {
+            fn from_context(_: &PanicContext) -> Self {
+                Region("eu")
+            }
+        }
+
+        #[derive(AsyncFromContext)]
+        #[context(PanicContext)]
+        struct TokenHandler {
+            token: Token,
+            region: Region,
+        }
+
+        #[derive(AsyncFromContext)]
+        #[context(C: Context)]
+        struct Fetched<T> {
+            token: T,
+        }
+
+        impl Handler for TokenHandler {
+            type Event = PanicEvent;
+            async fn handle(&self, _event: Self::Event) -> Result<(), Error> {
+                assert_eq!(self.token.0, "fetched");
+                Ok(())
+            }
+        }
+
+        let handler: TokenHandler = PanicContext.provide_async().await;
+        assert_eq!(handler.token.0, "fetched");
+        assert_eq!(handler.region.0, "eu");
+
+        let fetched: Fetched<Token> = PanicContext.provide_async().await;
+        assert_eq!(fetched.token.0, "fetched");
+
+        let mut router = super::Router::<PanicContext>::default();
+        router.add_async::<TokenHandler>();
+
+        let dispatch = router
+            .dispatch(&Arc::new(PanicContext), &PanicEvent, CorrelationId::new())
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        assert!(dispatch.fut.await.is_ok());
+    }
+
+    #[tokio::test]
+    async fn resolves_scoped_handlers() {
+        use std::sync::atomic::{AtomicBool, Ordering};
+
+        use crate::context::{InScope, Scope, Scoped};
+
+        #[derive(Debug, PartialEq)]
+        struct Tenant(&'static str);
+
+        // Like a transaction, ended once dropped
+        struct Transaction(Arc<AtomicBool>);
+
+        impl Drop for Transaction {
+            fn drop(&mut self) {
+                self.0.store(true, Ordering::SeqCst);
+            }
+        }
+
+        impl FromContext<PanicContext> for Tenant {
+            fn from_context(_: &PanicContext) -> Self {
+                Tenant("default")
+            }
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        struct Region(&'static str);
+
+        impl FromContext<PanicContext> for Region {
+            fn from_context(_: &PanicContext) -> Self {
+                Region("default")
+            }
+        }
+
+        #[derive(Clone, Debug, PartialEq)]
+        struct Locale(&'static str);
+
+        impl FromContext<PanicContext> for Locale {
+            fn from_context(_: &PanicContext) -> Self {
+                Locale("en")
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Scope<PanicContext>)]
+        struct ScopedHandler {
+            tenant: Scoped<Tenant>,
+            transaction: InScope<Transaction>,
+            region: Region,
+            locale: Locale,
+            info: super::EventInfo,
+        }
+
+        impl Handler for ScopedHandler {
+            type Event = PanicEvent;
+            async fn handle(&self, _event: Self::Event) -> Result<(), Error> {
+                assert_eq!(*self.tenant, Tenant("acme"));
+                let Transaction(ended) = &*self.transaction;
+                assert!(!ended.load(Ordering::SeqCst));
+                // Plain fields come from the scope first, then from the parent
+                assert_eq!(self.region, Region("eu"));
+                assert_eq!(self.locale, Locale("en"));
+                assert_eq!(self.info.name, "PanicEvent");
+                Ok(())
+            }
+        }
+
+        let ended = Arc::new(AtomicBool::new(false));
+        let mut router = super::Router::<PanicContext>::default();
+        router.add_scoped::<ScopedHandler>();
+        router.set_scope_init({
+            let ended = ended.clone();
+            move |scope, event| {
+                assert_eq!(event.name(), "PanicEvent");
+                scope.insert(Tenant("acme"));
+                scope.insert(Region("eu"));
+                scope.insert(Transaction(ended.clone()));
+            }
+        });
+
+        let dispatch = router
+            .dispatch(&Arc::new(PanicContext), &PanicEvent, CorrelationId::new())
+            .unwrap()
+            .pop()
+            .unwrap();
+
+        // The handler call keeps the scope once the dispatch returned
+        assert!(!ended.load(Ordering::SeqCst));
+        assert!(dispatch.fut.await.is_ok());
+        assert!(ended.load(Ordering::SeqCst));
+
+        let scope = Scope::new(PanicContext);
+        let tenant: Scoped<Tenant> = scope.provide();
+        assert_eq!(*tenant.into_inner(), Tenant("default"));
+    }
 }
@@ -0,0 +1,38 @@
+use std::{
+    any::Any,
+    future::Future,
+    panic::{catch_unwind, AssertUnwindSafe},
+    pin::Pin,
+    task::{Context, Poll},
+};
+
+use crate::events::Error;
+
+/// Turns a panic while polling the handler into [`Error::Panic`]
+pub(crate) struct CatchPanic<F>(pub(crate) F);
+
+impl<F, T> Future for CatchPanic<F>
+where
+    F: Future<Output = Result<T, Error>> + Unpin,
+{
+    type Output = Result<T, Error>;
+
+    fn poll(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Self::Output> {
+        let fut = &mut self.0;
+        match catch_unwind(AssertUnwindSafe(|| Pin::new(fut).poll(cx))) {
+            Ok(poll) => poll,
+            Err(payload) => Poll::Ready(Err(Error::Panic(panic_message(payload)))),
+        }
+    }
+}
+
+pub(crate) fn panic_message(payload: Box<dyn Any + Send>) -> String {
+    if let Some(msg) = payload.downcast_ref::<&'static str>() {
+        return msg.to_string();
+    }
+
+    match payload.downcast::<String>() {
+        Ok(msg) => *msg,
+        Err(_) => "Box<dyn Any>".to_string(),
+    }
+}
@@ -1,3 +1,6 @@
+// Lets the derive macros refer to `::cream` from inside this crate too
+extern crate self as cream;
+
 /// config for providing repositories, EventBusPort, etc.
 pub mod context;
 /// ports & sockets for emitting & recieving events
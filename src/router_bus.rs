@@ -1,47 +1,282 @@
-use crate::{event_bus::EventBusSocket, events::router::Router, tasks::Tasks};
+mod limits;
+
+use std::{sync::Arc, time::Duration};
+
+use tokio::sync::{watch, OwnedSemaphorePermit};
+use tokio_util::{sync::CancellationToken, task::task_tracker::TaskTrackerToken};
+
+pub use limits::ConcurrencyLimits;
+
+use crate::{
+    event_bus::{Dispatching, EventBusSocket},
+    events::{
+        router::{catch_panic::CatchPanic, Dispatch, Router},
+        CorrelationId, DomainEvent, Error, Failure,
+    },
+    tasks::Tasks,
+};
+
+use limits::Semaphores;
+
+/// Receives every handler error, timeouts included
+pub type FailureHandler = Arc<dyn Fn(Failure) + Send + Sync>;
+
+/// Sees every event published on the bus, or taken from it before it is dispatched
+pub type EventObserver = Arc<dyn Fn(Arc<dyn DomainEvent>) + Send + Sync>;
+
+pub fn log_failure(failure: Failure) {
+    eprintln!("error: {}", failure);
+}
 
 pub struct RouterBus<C: 'static> {
     recv: EventBusSocket,
-    ctx: C,
+    ctx: Arc<C>,
     router: Router<C>,
     tasks: Tasks,
+    semaphores: Semaphores,
+    handler_timeout: Option<Duration>,
+    on_failure: FailureHandler,
+    on_event: Option<EventObserver>,
+    draining: Option<TaskTrackerToken>,
 }
 
 impl<C: 'static> RouterBus<C> {
     pub fn new(socket: EventBusSocket, ctx: C, router: Router<C>, tasks: Tasks) -> Self {
+        // Taken right away, a shutdown starting before `listen` runs still waits for the bus
+        let draining = socket.signal().drain_guard();
         RouterBus {
             recv: socket,
-            ctx,
+            ctx: Arc::new(ctx),
             router,
             tasks,
+            semaphores: Semaphores::default(),
+            handler_timeout: None,
+            on_failure: Arc::new(log_failure),
+            on_event: None,
+            draining: Some(draining),
         }
     }
+
+    pub fn with_limits(mut self, limits: &ConcurrencyLimits) -> Self {
+        self.semaphores = limits.semaphores();
+        self
+    }
+
+    /// Timeout for handlers added without one of their own
+    pub fn with_handler_timeout(mut self, timeout: Option<Duration>) -> Self {
+        self.handler_timeout = timeout;
+        self
+    }
+
+    pub fn with_failure_handler(mut self, on_failure: FailureHandler) -> Self {
+        self.on_failure = on_failure;
+        self
+    }
+
+    pub fn with_event_observer(mut self, on_event: Option<EventObserver>) -> Self {
+        self.on_event = on_event;
+        self
+    }
 }
 
 impl<C: 'static> RouterBus<C> {
     pub async fn listen_once(&mut self) -> Option<()> {
-        let event = self.recv.recv().await?;
+        let (event, correlation, dispatching) = self.recv.recv_dispatching().await?;
+        self.handle(event, correlation, dispatching).await;
+        Some(())
+    }
+
+    /// Every handler call takes a global permit here before its handler is resolved, so the
+    /// listener stops taking events while the router is saturated
+    async fn handle(
+        &mut self,
+        event: Arc<dyn DomainEvent>,
+        correlation: CorrelationId,
+        _dispatching: Dispatching,
+    ) {
+        if let Some(on_event) = &self.on_event {
+            on_event(event.clone());
+        }
+
+        let mut first = limits::acquire(self.semaphores.global()).await;
         let (name, version) = (event.name(), event.version());
-        let Some(fut) = self.router.call(&self.ctx, event) else {
+        let Some(dispatches) = self.router.dispatch(&self.ctx, &*event, correlation) else {
             println!("warning: got unhandable event, {}@{}", name, version);
-            return Some(());
+            return;
         };
 
-        self.tasks.spawn(fut);
-        Some(())
+        for dispatch in dispatches {
+            let global = match first.take() {
+                Some(permit) => Some(permit),
+                None => limits::acquire(self.semaphores.global()).await,
+            };
+            self.spawn(dispatch, global);
+        }
     }
 
+    /// The call gives its global permit back while waiting for its handler permit, so a handler
+    /// at its own limit never holds global permits other handlers could run with
+    fn spawn(&mut self, dispatch: Dispatch, permit: Option<OwnedSemaphorePermit>) {
+        let Dispatch {
+            handler,
+            handler_name,
+            event_name,
+            timeout,
+            fut,
+        } = dispatch;
+
+        let waiting = self.recv.metrics().wait();
+        let semaphores = (self.semaphores.handler(handler), self.semaphores.global());
+
+        let timeout = timeout.or(self.handler_timeout);
+        let on_failure = self.on_failure.clone();
+        let fatal_panics = self
+            .router
+            .fatal_panics()
+            .then(|| self.recv.signal().clone());
+
+        self.tasks.spawn_named(handler_name, async move {
+            let (handler, global) = semaphores;
+            let (_handler, global) = limits::acquire_handler(handler, global, permit).await;
+            let _running = waiting.run();
+
+            // The handler is only resolved now, once both permits are held
+            let result = match timeout {
+                Some(timeout) => tokio::time::timeout(timeout, fut)
+                    .await
+                    .unwrap_or(Err(Error::Timeout(timeout))),
+                None => fut.await,
+            };
+
+            drop(global);
+
+            let Err(error) = result else {
+                return;
+            };
+
+            // Raising it again here would only end this detached task, the shutdown reports it
+            if let (Error::Panic(msg), Some(signal)) = (&error, &fatal_panics) {
+                signal.record_panic(format!("{}: {}", handler_name, msg));
+            }
+
+            on_failure(Failure {
+                handler: handler_name.into(),
+                event: Some(event_name),
+                error,
+            });
+        });
+    }
+
+    /// Listens until every port is dropped, the bus is drained on shutdown or the tasks are closed
     pub async fn listen(&mut self) {
-        while self.listen_once().await.is_some() {}
+        self.listen_until(&CancellationToken::new()).await;
+    }
+
+    /// Like [`listen`](Self::listen), but also stops taking events once `stop` is cancelled
+    pub async fn listen_until(&mut self, stop: &CancellationToken) -> RouterStatus {
+        let _draining = self.draining.take();
+        loop {
+            // Only receiving is interrupted, an event being dispatched is always dispatched
+            let received = tokio::select! {
+                biased;
+                _ = stop.cancelled() => return RouterStatus::Stopped,
+                _ = self.tasks.closed() => return RouterStatus::Stopped,
+                received = self.recv.recv_dispatching() => received,
+            };
+
+            let Some((event, correlation, dispatching)) = received else {
+                return RouterStatus::Finished;
+            };
+            self.handle(event, correlation, dispatching).await;
+        }
+    }
+}
+
+impl<C: Send + Sync + 'static> RouterBus<C> {
+    /// Listens in a task of [`Tasks`], so the shutdown waits for it
+    pub fn start(mut self) -> RouterHandle {
+        let stop = CancellationToken::new();
+        let (report, status) = watch::channel(RouterStatus::Running);
+
+        let tasks = self.tasks.clone();
+        tasks.spawn_named("router listener", {
+            let stop = stop.clone();
+            async move {
+                let listening = Box::pin(async move { Ok(self.listen_until(&stop).await) });
+                let status = CatchPanic(listening).await.unwrap_or_else(|error| {
+                    eprintln!("error: router listener failed: {}", error);
+                    RouterStatus::Failed(error.to_string())
+                });
+
+                report.send_replace(status);
+            }
+        });
+
+        RouterHandle { stop, status }
+    }
+}
+
+/// Where the listener of a [`RouterHandle`] is at
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum RouterStatus {
+    Running,
+    /// Every port was dropped, or the bus was drained on shutdown
+    Finished,
+    /// Stopped by [`RouterHandle::stop`], or by closing its [`Tasks`]
+    Stopped,
+    /// The listener panicked, with the error
+    Failed(String),
+    /// The listener task was aborted, like at the shutdown deadline
+    Aborted,
+}
+
+/// Controls the listener started by [`RouterBus::start`], dropping it leaves the listener running
+#[derive(Clone)]
+pub struct RouterHandle {
+    stop: CancellationToken,
+    status: watch::Receiver<RouterStatus>,
+}
+
+impl RouterHandle {
+    /// Stops taking events from the bus, handler calls already spawned keep running
+    ///
+    /// The events still queued are dropped, and counted in
+    /// [`ShutdownReport::dropped_events`](crate::tasks::ShutdownReport::dropped_events)
+    pub fn stop(&self) {
+        self.stop.cancel();
+    }
+
+    pub fn status(&self) -> RouterStatus {
+        let status = self.status.borrow().clone();
+        match status {
+            // The sender is only dropped without a status when the task is aborted
+            RouterStatus::Running if self.status.has_changed().is_err() => RouterStatus::Aborted,
+            status => status,
+        }
+    }
+
+    pub fn is_running(&self) -> bool {
+        self.status() == RouterStatus::Running
+    }
+
+    /// Waits for the listener to end, returning why it did
+    pub async fn join(&self) -> RouterStatus {
+        let mut status = self.status.clone();
+        let _ = status
+            .wait_for(|status| *status != RouterStatus::Running)
+            .await;
+
+        self.status()
     }
 }
 
 #[cfg(test)]
 mod tests {
+    use std::sync::Arc;
 
     use crate::{
         context::{events_context::EventsContextBuilder, Context, CreamContext, FromContext},
-        event_bus::EventBusPort,
+        event_bus::{BusMetrics, EventBusPort},
         events::{DomainEvent, Error, Handler},
     };
 
@@ -85,18 +320,543 @@ mod tests {
         router.add::<MyHandler>();
 
         let (events_ctx, setup) = EventsContextBuilder::default().build(&cream_ctx);
-        setup.setup(router, ctx);
+        let handle = setup.setup(router, ctx);
 
         let tasks: Tasks = cream_ctx.provide();
         let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
 
         port.publish(MyEvent);
+        metrics.idle().await;
+
+        // The listener is a task too, closing the tasks stops it
+        assert!(handle.is_running());
         tasks.close();
         tasks.wait().await;
+        assert_eq!(handle.status(), RouterStatus::Stopped);
 
         assert!(VAL.load(std::sync::atomic::Ordering::Relaxed));
     }
 
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn respects_concurrency_limits() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
+
+        use tokio::sync::Semaphore;
+
+        static RUNNING: AtomicUsize = AtomicUsize::new(0);
+        static MAX_RUNNING: AtomicUsize = AtomicUsize::new(0);
+        // Slow handlers add a permit once running, and wait for the gate to finish
+        static STARTED: Semaphore = Semaphore::const_new(0);
+        static GATE: Semaphore = Semaphore::const_new(0);
+        static FAST: Semaphore = Semaphore::const_new(0);
+
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct SlowEvent;
+        impl DomainEvent for SlowEvent {
+            fn name(&self) -> &'static str {
+                "SlowEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(Clone)]
+        struct FastEvent;
+        impl DomainEvent for FastEvent {
+            fn name(&self) -> &'static str {
+                "FastEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Ctx)]
+        struct SlowHandler;
+
+        impl Handler for SlowHandler {
+            type Event = SlowEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                let running = RUNNING.fetch_add(1, Ordering::SeqCst) + 1;
+                MAX_RUNNING.fetch_max(running, Ordering::SeqCst);
+                STARTED.add_permits(1);
+
+                GATE.acquire().await.unwrap().forget();
+                RUNNING.fetch_sub(1, Ordering::SeqCst);
+                Ok(())
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Ctx)]
+        struct FastHandler;
+
+        impl Handler for FastHandler {
+            type Event = FastEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                FAST.add_permits(1);
+                Ok(())
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+
+        let mut router = Router::<Ctx>::default();
+        router.add::<SlowHandler>();
+        router.add::<FastHandler>();
+
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_concurrency_limit(4)
+            .with_handler_concurrency_limit::<SlowHandler>(2)
+            .build(&cream_ctx);
+        setup.setup(router, Ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
+
+        for _ in 0..6 {
+            port.publish(SlowEvent);
+        }
+        port.publish(FastEvent);
+
+        let limit = Duration::from_secs(5);
+        tokio::time::timeout(limit, STARTED.acquire_many(2))
+            .await
+            .expect("two slow handlers should be running")
+            .unwrap()
+            .forget();
+
+        // The slow calls over their limit hold no global permit, the fast handler still runs
+        tokio::time::timeout(limit, FAST.acquire())
+            .await
+            .expect("the fast handler should not be starved")
+            .unwrap()
+            .forget();
+        assert_eq!(RUNNING.load(Ordering::SeqCst), 2);
+        assert_eq!(metrics.waiting_dispatches(), 4);
+
+        GATE.add_permits(6);
+        tokio::time::timeout(limit, metrics.idle())
+            .await
+            .expect("every handler should have finished");
+
+        assert_eq!(MAX_RUNNING.load(Ordering::SeqCst), 2);
+        assert_eq!(metrics.queue_depth(), 0);
+        assert_eq!(metrics.running_handlers(), 0);
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    async fn resolves_handlers_once_they_hold_a_permit() {
+        use std::{
+            sync::atomic::{AtomicUsize, Ordering},
+            time::Duration,
+        };
+
+        use tokio::sync::Semaphore;
+
+        static RESOLVED: AtomicUsize = AtomicUsize::new(0);
+        static STARTED: Semaphore = Semaphore::const_new(0);
+        static GATE: Semaphore = Semaphore::const_new(0);
+
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        // Stands for a handler opening a connection when resolved
+        struct DbHandler;
+        impl FromContext<Ctx> for DbHandler {
+            fn from_context(_: &Ctx) -> Self {
+                RESOLVED.fetch_add(1, Ordering::SeqCst);
+                DbHandler
+            }
+        }
+
+        impl Handler for DbHandler {
+            type Event = MyEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                STARTED.add_permits(1);
+                GATE.acquire().await.unwrap().forget();
+                Ok(())
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let mut router = Router::<Ctx>::default();
+        router.add::<DbHandler>();
+
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_channel_size(100)
+            .with_concurrency_limit(2)
+            .build(&cream_ctx);
+        setup.setup(router, Ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
+        for _ in 0..20 {
+            port.publish(MyEvent);
+        }
+
+        let limit = Duration::from_secs(5);
+        tokio::time::timeout(limit, STARTED.acquire_many(2))
+            .await
+            .expect("two handlers should be running")
+            .unwrap()
+            .forget();
+        tokio::time::sleep(Duration::from_millis(50)).await;
+
+        // The listener waits for a permit instead of resolving the whole burst
+        assert_eq!(RESOLVED.load(Ordering::SeqCst), 2);
+        assert_eq!(metrics.queued_events(), 17);
+
+        GATE.add_permits(20);
+        tokio::time::timeout(limit, metrics.idle())
+            .await
+            .expect("every handler should have finished");
+        assert_eq!(RESOLVED.load(Ordering::SeqCst), 20);
+    }
+
+    #[tokio::test]
+    async fn times_out_handlers() {
+        use std::{sync::Mutex, time::Duration};
+
+        use crate::events::router::HandlerOptions;
+
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Ctx)]
+        struct StuckHandler;
+
+        impl Handler for StuckHandler {
+            type Event = MyEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                tokio::time::sleep(Duration::from_secs(60)).await;
+                Ok(())
+            }
+        }
+
+        let failures = Arc::new(Mutex::new(vec![]));
+        let cream_ctx = CreamContext::default();
+
+        let mut router = Router::<Ctx>::default();
+        router.add_with::<StuckHandler>(
+            HandlerOptions::default().with_timeout(Duration::from_millis(50)),
+        );
+
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_handler_timeout(Duration::from_secs(30))
+            .with_failure_handler({
+                let failures = failures.clone();
+                move |failure| failures.lock().unwrap().push(failure)
+            })
+            .build(&cream_ctx);
+        setup.setup(router, Ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
+        port.publish(MyEvent);
+
+        tokio::time::timeout(Duration::from_secs(5), metrics.idle())
+            .await
+            .expect("stuck handler should have been timed out");
+
+        let failures = failures.lock().unwrap();
+        assert_eq!(failures.len(), 1);
+        assert!(matches!(
+            failures[0].error,
+            Error::Timeout(timeout) if timeout == Duration::from_millis(50)
+        ));
+    }
+
+    #[tokio::test]
+    async fn reports_listener_failures() {
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_event_observer(|_| panic!("observer"))
+            .build(&cream_ctx);
+        let handle = setup.setup(Router::<Ctx>::default(), Ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        port.publish(MyEvent);
+
+        assert_eq!(
+            handle.join().await,
+            RouterStatus::Failed("panicked: observer".to_string())
+        );
+        assert!(!handle.is_running());
+    }
+
+    #[tokio::test]
+    async fn drops_queued_events_on_stop() {
+        use crate::tasks::Shutdown;
+
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_channel_size(2)
+            .build(&cream_ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
+        for _ in 0..4 {
+            port.publish(MyEvent);
+        }
+        tokio::task::yield_now().await;
+        assert_eq!(metrics.queue_depth(), 4);
+
+        // Stopped before taking any, two are queued and the others still sending
+        let handle = setup.setup(Router::<Ctx>::default(), Ctx);
+        handle.stop();
+
+        tokio::time::timeout(Duration::from_secs(5), metrics.idle())
+            .await
+            .expect("the dropped events should leave the bus idle");
+        assert_eq!(handle.join().await, RouterStatus::Stopped);
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        assert_eq!(shutdown.run().await.dropped_events, 4);
+    }
+
+    #[tokio::test]
+    async fn reports_fatal_panics_at_shutdown() {
+        use crate::tasks::Shutdown;
+
+        struct Ctx;
+        impl Context for Ctx {}
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Ctx)]
+        struct PanicHandler;
+
+        impl Handler for PanicHandler {
+            type Event = MyEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                panic!("boom");
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let mut router = Router::<Ctx>::default();
+        router.add::<PanicHandler>();
+        router.set_fatal_panics(true);
+
+        let (events_ctx, setup) = EventsContextBuilder::default()
+            .with_failure_handler(|_| {})
+            .build(&cream_ctx);
+        setup.setup(router, Ctx);
+
+        let port: EventBusPort = events_ctx.provide();
+        let metrics: BusMetrics = events_ctx.provide();
+        port.publish(MyEvent);
+        metrics.idle().await;
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        let report = shutdown.run().await;
+        assert_eq!(report.handler_panics.len(), 1);
+        assert!(report.handler_panics[0].ends_with("PanicHandler: boom"));
+        assert!(!report.is_clean());
+    }
+
+    #[tokio::test]
+    async fn correlates_events_published_by_handlers() {
+        use std::sync::Mutex;
+
+        use crate::{
+            context::{events_context::EventsContext, Scope},
+            events::{router::EventInfo, CorrelationId},
+        };
+
+        type Seen = Arc<Mutex<Vec<(&'static str, u32, CorrelationId)>>>;
+
+        #[derive(Clone, Context)]
+        struct Ctx {
+            #[extend]
+            events: EventsContext,
+            #[provide]
+            seen: Seen,
+        }
+
+        #[derive(Clone)]
+        struct Placed(u32);
+        impl DomainEvent for Placed {
+            fn name(&self) -> &'static str {
+                "Placed"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(Clone)]
+        struct Shipped(u32);
+        impl DomainEvent for Shipped {
+            fn name(&self) -> &'static str {
+                "Shipped"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Ctx)]
+        struct ShipHandler {
+            port: EventBusPort,
+        }
+
+        impl Handler for ShipHandler {
+            type Event = Placed;
+            async fn handle(&self, Placed(id): Placed) -> Result<(), Error> {
+                self.port.publish(Shipped(id));
+                Ok(())
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Scope<Ctx>)]
+        struct PlacedRecorder {
+            info: EventInfo,
+            seen: Seen,
+        }
+
+        impl Handler for PlacedRecorder {
+            type Event = Placed;
+            async fn handle(&self, Placed(id): Placed) -> Result<(), Error> {
+                let seen = (self.info.name, id, self.info.correlation_id);
+                self.seen.lock().unwrap().push(seen);
+                Ok(())
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(Scope<Ctx>)]
+        struct ShippedRecorder {
+            info: EventInfo,
+            seen: Seen,
+        }
+
+        impl Handler for ShippedRecorder {
+            type Event = Shipped;
+            async fn handle(&self, Shipped(id): Shipped) -> Result<(), Error> {
+                let seen = (self.info.name, id, self.info.correlation_id);
+                self.seen.lock().unwrap().push(seen);
+                Ok(())
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let mut router = Router::<Ctx>::default();
+        router.add::<ShipHandler>();
+        router.add_scoped::<PlacedRecorder>();
+        router.add_scoped::<ShippedRecorder>();
+
+        let (events, setup) = EventsContextBuilder::default().build(&cream_ctx);
+        let seen = Seen::default();
+        setup.setup(
+            router,
+            Ctx {
+                events: events.clone(),
+                seen: seen.clone(),
+            },
+        );
+
+        let port: EventBusPort = events.provide();
+        let metrics: BusMetrics = events.provide();
+        port.publish(Placed(1));
+        port.publish(Placed(2));
+        metrics.idle().await;
+
+        let seen = seen.lock().unwrap();
+        let correlation = |name, id| {
+            seen.iter()
+                .find(|seen| seen.0 == name && seen.1 == id)
+                .map(|seen| seen.2)
+                .unwrap()
+        };
+        assert_eq!(seen.len(), 4);
+        assert_eq!(correlation("Placed", 1), correlation("Shipped", 1));
+        assert_eq!(correlation("Placed", 2), correlation("Shipped", 2));
+        assert_ne!(correlation("Placed", 1), correlation("Placed", 2));
+    }
+
     #[test]
     fn can_build_ctx_with_cream() {
         #[allow(dead_code)]
@@ -0,0 +1,84 @@
+use std::{any::TypeId, collections::HashMap, sync::Arc};
+
+use tokio::sync::{OwnedSemaphorePermit, Semaphore, TryAcquireError};
+
+use crate::events::Handler;
+
+/// How many handler calls may run at the same time, globally and per handler
+#[derive(Clone, Default)]
+pub struct ConcurrencyLimits {
+    global: Option<usize>,
+    handlers: HashMap<TypeId, usize>,
+}
+
+impl ConcurrencyLimits {
+    pub fn with_global(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "concurrency limit must be greater than 0");
+        self.global = Some(limit);
+        self
+    }
+
+    pub fn with_handler<H: Handler + 'static>(mut self, limit: usize) -> Self {
+        assert!(limit > 0, "concurrency limit must be greater than 0");
+        self.handlers.insert(TypeId::of::<H>(), limit);
+        self
+    }
+
+    pub(crate) fn semaphores(&self) -> Semaphores {
+        Semaphores {
+            global: self.global.map(|limit| Arc::new(Semaphore::new(limit))),
+            handlers: self
+                .handlers
+                .iter()
+                .map(|(id, limit)| (*id, Arc::new(Semaphore::new(*limit))))
+                .collect(),
+        }
+    }
+}
+
+#[derive(Default)]
+pub(crate) struct Semaphores {
+    global: Option<Arc<Semaphore>>,
+    handlers: HashMap<TypeId, Arc<Semaphore>>,
+}
+
+impl Semaphores {
+    pub(crate) fn global(&self) -> Option<Arc<Semaphore>> {
+        self.global.clone()
+    }
+
+    pub(crate) fn handler(&self, handler: TypeId) -> Option<Arc<Semaphore>> {
+        self.handlers.get(&handler).cloned()
+    }
+}
+
+pub(crate) async fn acquire(semaphore: Option<Arc<Semaphore>>) -> Option<OwnedSemaphorePermit> {
+    let semaphore = semaphore?;
+    let permit = semaphore
+        .acquire_owned()
+        .await
+        .expect("concurrency semaphores are never closed");
+
+    Some(permit)
+}
+
+/// Takes the permit of the handler, giving `permit` back to `global` while it has to wait for it
+pub(crate) async fn acquire_handler(
+    handler: Option<Arc<Semaphore>>,
+    global: Option<Arc<Semaphore>>,
+    permit: Option<OwnedSemaphorePermit>,
+) -> (Option<OwnedSemaphorePermit>, Option<OwnedSemaphorePermit>) {
+    let Some(handler) = handler else {
+        return (None, permit);
+    };
+
+    match handler.clone().try_acquire_owned() {
+        Ok(handler) => (Some(handler), permit),
+        Err(TryAcquireError::NoPermits) => {
+            drop(permit);
+            let handler = acquire(Some(handler)).await;
+            (handler, acquire(global).await)
+        }
+        Err(TryAcquireError::Closed) => unreachable!("concurrency semaphores are never closed"),
+    }
+}
@@ -0,0 +1,162 @@
+use std::{
+    sync::{
+        atomic::{AtomicUsize, Ordering},
+        Arc, Mutex,
+    },
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tokio_util::{
+    sync::CancellationToken,
+    task::{task_tracker::TaskTrackerToken, TaskTracker},
+};
+
+use super::{Supervisor, Tasks};
+use crate::context::{CreamContext, FromContext};
+
+/// Shared between [`Shutdown`] and the event bus, tells the bus when to stop taking events
+#[derive(Clone, Default)]
+pub struct ShutdownSignal {
+    stopping: CancellationToken,
+    draining: TaskTracker,
+    dropped: Arc<AtomicUsize>,
+    panics: Arc<Mutex<Vec<String>>>,
+}
+
+impl ShutdownSignal {
+    pub fn is_stopping(&self) -> bool {
+        self.stopping.is_cancelled()
+    }
+
+    pub async fn stopping(&self) {
+        self.stopping.cancelled().await;
+    }
+
+    /// Kept alive by whatever must be drained before the tasks are awaited
+    pub(crate) fn drain_guard(&self) -> TaskTrackerToken {
+        self.draining.token()
+    }
+
+    pub(crate) fn record_dropped(&self) {
+        self.dropped.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// A handler panicked on the bus while its router had fatal panics
+    pub(crate) fn record_panic(&self, panic: String) {
+        self.panics.lock().unwrap().push(panic);
+    }
+
+    pub(crate) fn stop(&self) {
+        self.stopping.cancel();
+        self.draining.close();
+    }
+
+    async fn drained(&self) {
+        self.draining.wait().await;
+    }
+}
+
+/// What a [`Shutdown`] could not finish
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub struct ShutdownReport {
+    /// Events published once the shutdown had started
+    pub dropped_events: usize,
+    /// Tasks still running at the deadline
+    pub aborted_tasks: usize,
+    /// Panics of handlers whose router has fatal panics, as `handler: message`
+    pub handler_panics: Vec<String>,
+    pub elapsed: Duration,
+}
+
+impl ShutdownReport {
+    pub fn is_clean(&self) -> bool {
+        self.dropped_events == 0 && self.aborted_tasks == 0 && self.handler_panics.is_empty()
+    }
+}
+
+pub struct Shutdown {
+    tasks: Tasks,
+    signal: ShutdownSignal,
+    supervisor: Supervisor,
+    deadline: Duration,
+}
+
+impl FromContext<CreamContext> for Shutdown {
+    fn from_context(ctx: &CreamContext) -> Self {
+        Self {
+            tasks: FromContext::from_context(ctx),
+            signal: FromContext::from_context(ctx),
+            supervisor: FromContext::from_context(ctx),
+            deadline: Duration::from_secs(30),
+        }
+    }
+}
+
+impl Shutdown {
+    /// Time given to in-flight work before it gets aborted
+    pub fn with_deadline(mut self, deadline: Duration) -> Self {
+        self.deadline = deadline;
+        self
+    }
+
+    /// Stops the supervised services, stops accepting events, drains the bus, waits for the
+    /// running tasks until the deadline and aborts whatever is left
+    pub async fn run(self) -> ShutdownReport {
+        let started = Instant::now();
+        let deadline = started + self.deadline;
+
+        // Services first, so the events they published are still drained
+        let _ = tokio::time::timeout_at(deadline, self.supervisor.stop()).await;
+        self.signal.stop();
+        let _ = tokio::time::timeout_at(deadline, self.signal.drained()).await;
+
+        self.tasks.close();
+        self.tasks.cancel();
+
+        let finished = tokio::time::timeout_at(deadline, self.tasks.wait())
+            .await
+            .is_ok();
+
+        let aborted_tasks = if finished {
+            0
+        } else {
+            for task in self.tasks.snapshot() {
+                eprintln!("warning: aborting {} at the shutdown deadline", task);
+            }
+
+            let aborted = self.tasks.abort_all();
+            self.tasks.wait().await;
+            aborted
+        };
+
+        ShutdownReport {
+            dropped_events: self.signal.dropped.load(Ordering::SeqCst),
+            aborted_tasks,
+            handler_panics: self.signal.panics.lock().unwrap().clone(),
+            elapsed: started.elapsed(),
+        }
+    }
+
+    /// Runs the shutdown once the process gets SIGINT or SIGTERM
+    pub async fn run_on_signal(self) -> ShutdownReport {
+        wait_for_signal().await;
+        self.run().await
+    }
+}
+
+#[cfg(unix)]
+async fn wait_for_signal() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    let mut terminate = signal(SignalKind::terminate()).expect("failed to listen for SIGTERM");
+    tokio::select! {
+        _ = tokio::signal::ctrl_c() => {}
+        _ = terminate.recv() => {}
+    }
+}
+
+#[cfg(not(unix))]
+async fn wait_for_signal() {
+    let _ = tokio::signal::ctrl_c().await;
+}
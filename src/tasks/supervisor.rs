@@ -0,0 +1,435 @@
+use std::{
+    error::Error as StdError,
+    future::Future,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use tokio::time::Instant;
+use tokio_util::{sync::CancellationToken, task::TaskTracker};
+
+use super::Tasks;
+use crate::{
+    events::{router::catch_panic::CatchPanic, Error, Failure},
+    router_bus::{log_failure, FailureHandler},
+};
+
+/// A run lasting this long is healthy, the failures before it are forgotten
+const HEALTHY_AFTER: Duration = Duration::from_secs(10);
+
+/// When a failed service is started again, services are always restarted one for one
+#[derive(Clone, Debug)]
+pub enum Restart {
+    Never,
+    /// Right away, giving up after `max_restarts` failures in a row
+    Immediate {
+        max_restarts: usize,
+    },
+    Backoff(Backoff),
+}
+
+impl Default for Restart {
+    fn default() -> Self {
+        Self::Backoff(Backoff::default())
+    }
+}
+
+impl Restart {
+    /// `None` once the service should not be restarted anymore
+    fn delay(&self, failures: usize) -> Option<Duration> {
+        match self {
+            Self::Never => None,
+            Self::Immediate { max_restarts } => {
+                (failures <= *max_restarts).then_some(Duration::ZERO)
+            }
+            Self::Backoff(backoff) => backoff.delay(failures),
+        }
+    }
+}
+
+/// Waits `initial` before the first restart, doubling it after every failure up to `max`
+#[derive(Clone, Debug)]
+pub struct Backoff {
+    initial: Duration,
+    max: Duration,
+    max_restarts: Option<usize>,
+}
+
+impl Default for Backoff {
+    fn default() -> Self {
+        Self::new(Duration::from_millis(100), Duration::from_secs(30))
+    }
+}
+
+impl Backoff {
+    pub fn new(initial: Duration, max: Duration) -> Self {
+        Self {
+            initial,
+            max,
+            max_restarts: None,
+        }
+    }
+
+    /// Gives up after that many failures in a row, never by default
+    pub fn with_max_restarts(mut self, max_restarts: usize) -> Self {
+        self.max_restarts = Some(max_restarts);
+        self
+    }
+
+    fn delay(&self, failures: usize) -> Option<Duration> {
+        if self.max_restarts.is_some_and(|max| failures > max) {
+            return None;
+        }
+
+        let doublings = failures.saturating_sub(1).min(31) as u32;
+        Some(self.initial.saturating_mul(1 << doublings).min(self.max))
+    }
+}
+
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub enum ServiceStatus {
+    Running,
+    /// Waiting to be restarted, with its failures in a row and the last error
+    Restarting {
+        failures: usize,
+        error: String,
+    },
+    /// Returned without an error
+    Finished,
+    /// Stopped by [`Supervisor::stop`] or the shutdown
+    Stopped,
+    /// Not restarted anymore, with the last error
+    Failed(String),
+}
+
+struct Service {
+    name: String,
+    status: Mutex<ServiceStatus>,
+    stop: CancellationToken,
+    done: TaskTracker,
+}
+
+impl Service {
+    fn set(&self, status: ServiceStatus) {
+        *self.status.lock().unwrap() = status;
+    }
+}
+
+/// Runs long-lived services, like pollers and relays, as tasks of [`Tasks`] and restarts them
+/// when they fail or panic
+///
+/// The [`Shutdown`](super::Shutdown) stops them first, in reverse start order
+#[derive(Clone)]
+pub struct Supervisor {
+    tasks: Tasks,
+    services: Arc<Mutex<Vec<Arc<Service>>>>,
+    on_failure: FailureHandler,
+}
+
+impl Supervisor {
+    pub fn new(tasks: Tasks) -> Self {
+        Self {
+            tasks,
+            services: Arc::default(),
+            on_failure: Arc::new(log_failure),
+        }
+    }
+
+    /// Receives every failed run, before the service is restarted
+    pub fn with_failure_handler(mut self, on_failure: FailureHandler) -> Self {
+        self.on_failure = on_failure;
+        self
+    }
+
+    /// Supervises `service` with the default [`Restart`]
+    pub fn spawn<F, Fut, E>(&self, name: impl Into<String>, service: F) -> String
+    where
+        F: Fn(CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    {
+        self.spawn_with(name, Restart::default(), service)
+    }
+
+    /// `service` is called for every run, and should return once its token is cancelled
+    ///
+    /// Returns the name the service is listed under, `name#2`, `name#3`... when `name` is taken.
+    /// Services that ended are unlisted when the next one is spawned, freeing their name
+    pub fn spawn_with<F, Fut, E>(
+        &self,
+        name: impl Into<String>,
+        restart: Restart,
+        service: F,
+    ) -> String
+    where
+        F: Fn(CancellationToken) -> Fut + Send + Sync + 'static,
+        Fut: Future<Output = Result<(), E>> + Send + 'static,
+        E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+    {
+        let state = {
+            let mut services = self.services.lock().unwrap();
+            services.retain(|service| !service.done.is_empty());
+
+            let name = name.into();
+            let mut listed = name.clone();
+            for n in 2.. {
+                if !services.iter().any(|service| service.name == listed) {
+                    break;
+                }
+                listed = format!("{}#{}", name, n);
+            }
+
+            let state = Arc::new(Service {
+                name: listed,
+                status: Mutex::new(ServiceStatus::Running),
+                stop: self.tasks.cancellation_token(),
+                done: TaskTracker::new(),
+            });
+            state.done.close();
+            services.push(state.clone());
+            state
+        };
+
+        let supervised = supervise(state.clone(), restart, self.on_failure.clone(), service);
+        self.tasks
+            .spawn_named(state.name.clone(), state.done.track_future(supervised));
+        state.name.clone()
+    }
+
+    pub fn status(&self, name: &str) -> Option<ServiceStatus> {
+        let services = self.services.lock().unwrap();
+        let service = services.iter().find(|service| service.name == name)?;
+        let status = service.status.lock().unwrap().clone();
+        Some(status)
+    }
+
+    /// Every service with its status, in start order
+    pub fn services(&self) -> Vec<(String, ServiceStatus)> {
+        self.services
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|service| (service.name.clone(), service.status.lock().unwrap().clone()))
+            .collect()
+    }
+
+    /// Stops the services one at a time, the last started first
+    pub async fn stop(&self) {
+        let services = self.services.lock().unwrap().clone();
+        for service in services.iter().rev() {
+            service.stop.cancel();
+            service.done.wait().await;
+        }
+    }
+}
+
+async fn supervise<F, Fut, E>(
+    service: Arc<Service>,
+    restart: Restart,
+    on_failure: FailureHandler,
+    run: F,
+) where
+    F: Fn(CancellationToken) -> Fut,
+    Fut: Future<Output = Result<(), E>> + Send + 'static,
+    E: Into<Box<dyn StdError + Send + Sync>> + 'static,
+{
+    let mut failures = 0;
+    loop {
+        service.set(ServiceStatus::Running);
+        let started = Instant::now();
+
+        let fut = run(service.stop.clone());
+        let result = CatchPanic(Box::pin(async move {
+            fut.await.map_err(|err| Error::Other(err.into()))
+        }))
+        .await;
+
+        if service.stop.is_cancelled() {
+            service.set(ServiceStatus::Stopped);
+            return;
+        }
+
+        let error = match result {
+            Ok(()) => {
+                service.set(ServiceStatus::Finished);
+                return;
+            }
+            Err(error) => error,
+        };
+        let message = error.to_string();
+        on_failure(Failure {
+            handler: service.name.clone().into(),
+            event: None,
+            error,
+        });
+
+        if started.elapsed() >= HEALTHY_AFTER {
+            failures = 0;
+        }
+        failures += 1;
+
+        let Some(delay) = restart.delay(failures) else {
+            service.set(ServiceStatus::Failed(message));
+            return;
+        };
+        service.set(ServiceStatus::Restarting {
+            failures,
+            error: message,
+        });
+
+        tokio::select! {
+            _ = tokio::time::sleep(delay) => {}
+            _ = service.stop.cancelled() => {
+                service.set(ServiceStatus::Stopped);
+                return;
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    use crate::{
+        context::{Context, CreamContext},
+        tasks::Shutdown,
+    };
+
+    use super::*;
+
+    #[test]
+    fn doubles_backoff_delays() {
+        let restart = Restart::Backoff(
+            Backoff::new(Duration::from_millis(10), Duration::from_millis(50)).with_max_restarts(4),
+        );
+
+        let delays: Vec<_> = (1..=5).map(|failures| restart.delay(failures)).collect();
+        let ms = |ms| Some(Duration::from_millis(ms));
+        assert_eq!(delays, [ms(10), ms(20), ms(40), ms(50), None]);
+    }
+
+    #[tokio::test]
+    async fn restarts_failing_services() {
+        let runs = Arc::new(AtomicUsize::new(0));
+        let supervisor = Supervisor::new(Tasks::new());
+
+        supervisor.spawn_with(
+            "poller",
+            Restart::Backoff(Backoff::new(
+                Duration::from_millis(1),
+                Duration::from_millis(5),
+            )),
+            {
+                let runs = runs.clone();
+                move |token: CancellationToken| {
+                    let run = runs.fetch_add(1, Ordering::SeqCst);
+                    async move {
+                        if run < 2 {
+                            return Err("connection lost");
+                        }
+                        token.cancelled().await;
+                        Ok(())
+                    }
+                }
+            },
+        );
+
+        supervisor.spawn_with("relay", Restart::Immediate { max_restarts: 1 }, |_| async {
+            panic!("boom");
+            #[allow(unreachable_code)]
+            Ok::<_, &str>(())
+        });
+
+        tokio::time::timeout(Duration::from_secs(1), async {
+            let relay_failed = |status| matches!(status, Some(ServiceStatus::Failed(_)));
+            while runs.load(Ordering::SeqCst) < 3 || !relay_failed(supervisor.status("relay")) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("services should have been restarted");
+
+        assert_eq!(supervisor.status("poller"), Some(ServiceStatus::Running));
+        assert_eq!(
+            supervisor.status("relay"),
+            Some(ServiceStatus::Failed("panicked: boom".to_string()))
+        );
+
+        supervisor.stop().await;
+        assert_eq!(supervisor.status("poller"), Some(ServiceStatus::Stopped));
+    }
+
+    #[tokio::test]
+    async fn lists_services_by_unique_name() {
+        let failures = Arc::new(Mutex::new(Vec::new()));
+        let supervisor = Supervisor::new(Tasks::new()).with_failure_handler({
+            let failures = failures.clone();
+            Arc::new(move |failure: Failure| failures.lock().unwrap().push(failure.to_string()))
+        });
+
+        let idle = |token: CancellationToken| async move {
+            token.cancelled().await;
+            Ok::<_, &str>(())
+        };
+        assert_eq!(supervisor.spawn("worker", idle), "worker");
+        assert_eq!(supervisor.spawn("worker", idle), "worker#2");
+
+        let failing = supervisor.spawn_with("import", Restart::Never, |_| async {
+            Err::<(), _>("bad row")
+        });
+        tokio::time::timeout(Duration::from_secs(1), async {
+            while supervisor.status(&failing) == Some(ServiceStatus::Running) {
+                tokio::time::sleep(Duration::from_millis(1)).await;
+            }
+        })
+        .await
+        .expect("the import should have failed");
+        assert_eq!(
+            *failures.lock().unwrap(),
+            ["service import failed: bad row"]
+        );
+
+        // Ended services are unlisted by the next spawn
+        supervisor.spawn("import", idle);
+        let names: Vec<_> = supervisor
+            .services()
+            .into_iter()
+            .map(|(name, _)| name)
+            .collect();
+        assert_eq!(names, ["worker", "worker#2", "import"]);
+
+        supervisor.stop().await;
+    }
+
+    #[tokio::test]
+    async fn shutdown_stops_services_in_reverse_order() {
+        let stopped = Arc::new(Mutex::new(Vec::new()));
+        let cream_ctx = CreamContext::default();
+        let supervisor: Supervisor = cream_ctx.provide();
+
+        for name in ["first", "second"] {
+            let stopped = stopped.clone();
+            supervisor.spawn(name, move |token: CancellationToken| {
+                let stopped = stopped.clone();
+                async move {
+                    token.cancelled().await;
+                    stopped.lock().unwrap().push(name);
+                    Ok::<_, &str>(())
+                }
+            });
+        }
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        assert!(shutdown.run().await.is_clean());
+
+        assert_eq!(*stopped.lock().unwrap(), ["second", "first"]);
+        assert_eq!(
+            supervisor.services(),
+            [
+                ("first".to_string(), ServiceStatus::Stopped),
+                ("second".to_string(), ServiceStatus::Stopped),
+            ]
+        );
+    }
+}
@@ -1,32 +1,289 @@
-use std::future::Future;
-
-use tokio_util::task::TaskTracker;
+use std::{
+    collections::HashMap,
+    fmt,
+    future::Future,
+    panic::Location,
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        Arc, Mutex, Weak,
+    },
+    time::Duration,
+};
+
+use tokio::{
+    task::{AbortHandle, JoinError, JoinHandle},
+    time::Instant,
+};
+use tokio_util::{
+    sync::CancellationToken,
+    task::{task_tracker::TaskTrackerToken, TaskTracker},
+};
 
 #[derive(Default, Clone)]
-pub struct Tasks(TaskTracker);
+pub struct Tasks {
+    tracker: TaskTracker,
+    token: CancellationToken,
+    closed: CancellationToken,
+    running: Arc<Running>,
+    parent: Option<Arc<Tasks>>,
+}
+
+/// The tasks still running
+#[derive(Default)]
+struct Running {
+    next_id: AtomicU64,
+    tasks: Mutex<HashMap<u64, Entry>>,
+}
+
+#[derive(Clone)]
+struct Entry {
+    name: Option<String>,
+    started: Instant,
+    origin: &'static Location<'static>,
+    abort: AbortHandle,
+    /// The [`Running`] of every scope listing the task
+    scopes: Arc<[Weak<Running>]>,
+}
+
+impl Entry {
+    /// Unlists the task from every scope and aborts it, returning whether it was still running
+    fn abort(&self, id: u64) -> bool {
+        for running in self.scopes.iter().filter_map(Weak::upgrade) {
+            running.tasks.lock().unwrap().remove(&id);
+        }
+
+        let running = !self.abort.is_finished();
+        self.abort.abort();
+        running
+    }
+}
+
+/// Removes the task from the [`Running`] of its scope and their parents when it ends, even if
+/// aborted or panicking
+struct RunningGuard {
+    id: u64,
+    scopes: Vec<Arc<Running>>,
+}
+
+impl Drop for RunningGuard {
+    fn drop(&mut self) {
+        for running in &self.scopes {
+            running.tasks.lock().unwrap().remove(&self.id);
+        }
+    }
+}
+
+/// A running task, as listed by [`Tasks::snapshot`]
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct TaskInfo {
+    pub id: u64,
+    pub name: Option<String>,
+    pub age: Duration,
+    /// Where the task was spawned from
+    pub origin: &'static Location<'static>,
+}
+
+impl fmt::Display for TaskInfo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.name {
+            Some(name) => write!(f, "task `{}`", name)?,
+            None => write!(f, "task #{}", self.id)?,
+        }
+        write!(f, " spawned at {}, running for {:?}", self.origin, self.age)
+    }
+}
+
+/// Returned by [`Tasks::spawn_named`], dropping it leaves the task running
+pub struct TaskHandle<T> {
+    id: u64,
+    handle: JoinHandle<T>,
+}
+
+impl<T> TaskHandle<T> {
+    /// Same as in [`TaskInfo::id`]
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    pub fn abort(&self) {
+        self.handle.abort();
+    }
+
+    pub fn is_finished(&self) -> bool {
+        self.handle.is_finished()
+    }
+
+    /// Waits for the output of the task, failing if it was aborted or panicked
+    pub async fn join(self) -> Result<T, JoinError> {
+        self.handle.await
+    }
+}
 
 impl Tasks {
     pub fn new() -> Self {
-        Self(TaskTracker::new())
+        Self::default()
     }
 
+    #[track_caller]
     pub fn spawn<F>(&self, f: F)
     where
         F: Future + Send + 'static,
         F::Output: Send + 'static,
     {
-        self.0.spawn(f);
+        self.spawn_entry(None, Location::caller(), f);
+    }
+
+    /// Like [`spawn`](Self::spawn), the name shows in [`snapshot`](Self::snapshot)
+    #[track_caller]
+    pub fn spawn_named<F>(&self, name: impl Into<String>, f: F) -> TaskHandle<F::Output>
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let (id, handle) = self.spawn_entry(Some(name.into()), Location::caller(), f);
+        TaskHandle { id, handle }
     }
 
+    fn spawn_entry<F>(
+        &self,
+        name: Option<String>,
+        origin: &'static Location<'static>,
+        f: F,
+    ) -> (u64, JoinHandle<F::Output>)
+    where
+        F: Future + Send + 'static,
+        F::Output: Send + 'static,
+    {
+        let scopes: Vec<_> = self.scopes().collect();
+        let root = scopes.last().expect("a scope is its own first scope");
+
+        let guard = RunningGuard {
+            id: root.running.next_id.fetch_add(1, Ordering::Relaxed),
+            scopes: scopes.iter().map(|scope| scope.running.clone()).collect(),
+        };
+        // Counts toward every parent too, so waiting for them waits for it
+        let parents: Vec<TaskTrackerToken> = scopes[1..]
+            .iter()
+            .map(|scope| scope.tracker.token())
+            .collect();
+
+        // Holding the locks, in the order the guard takes them, makes sure it can not remove
+        // the task before it is inserted
+        let mut locked: Vec<_> = scopes
+            .iter()
+            .map(|scope| scope.running.tasks.lock().unwrap())
+            .collect();
+        let id = guard.id;
+        let handle = self.tracker.spawn(async move {
+            let _guard = guard;
+            let _parents = parents;
+            f.await
+        });
+
+        let entry = Entry {
+            name,
+            started: Instant::now(),
+            origin,
+            abort: handle.abort_handle(),
+            scopes: scopes
+                .iter()
+                .map(|scope| Arc::downgrade(&scope.running))
+                .collect(),
+        };
+        for tasks in &mut locked {
+            tasks.insert(id, entry.clone());
+        }
+
+        (id, handle)
+    }
+
+    /// Tasks closed, awaited and cancelled on their own, which still count as tasks of these
+    ///
+    /// Waiting for, closing, cancelling or aborting these includes the tasks of the child scope,
+    /// its tasks also show in their [`snapshot`](Self::snapshot)
+    pub fn child_scope(&self) -> Tasks {
+        Tasks {
+            tracker: TaskTracker::new(),
+            token: self.token.child_token(),
+            closed: self.closed.child_token(),
+            running: Arc::default(),
+            parent: Some(Arc::new(self.clone())),
+        }
+    }
+
+    /// These tasks, then their parent scopes up to the root
+    fn scopes(&self) -> impl Iterator<Item = &Tasks> {
+        std::iter::successors(Some(self), |scope| scope.parent.as_deref())
+    }
+
+    /// Every running task, the oldest first
+    pub fn snapshot(&self) -> Vec<TaskInfo> {
+        let mut tasks: Vec<_> = self
+            .running
+            .tasks
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(id, entry)| TaskInfo {
+                id: *id,
+                name: entry.name.clone(),
+                age: entry.started.elapsed(),
+                origin: entry.origin,
+            })
+            .collect();
+
+        tasks.sort_by_key(|task| task.id);
+        tasks
+    }
+
+    /// Aborts a single task, returning whether it was still running
+    pub fn abort(&self, id: u64) -> bool {
+        let entry = self.running.tasks.lock().unwrap().remove(&id);
+        entry.is_some_and(|entry| entry.abort(id))
+    }
+
+    /// Waits for the tasks once closed, those spawned after it returns are not waited for
+    ///
+    /// Events still on their way through the bus are not tasks yet, wait for
+    /// [`BusMetrics::idle`](crate::event_bus::BusMetrics::idle) first
     pub async fn wait(&self) {
-        // By wating twice, we ensure that the tasks are completed
-        // I think a single wait should be enough, but just works if there are two wait
-        self.0.wait().await;
-        self.0.wait().await;
+        tokio::select! {
+            _ = self.tracker.wait() => {}
+            // Closed by a parent scope
+            _ = self.closed.cancelled() => {
+                self.tracker.close();
+                self.tracker.wait().await;
+            }
+        }
     }
 
+    /// Also stops a router listener started in these tasks, dropping the events still queued
     pub fn close(&self) {
-        self.0.close();
+        self.tracker.close();
+        self.closed.cancel();
+    }
+
+    pub(crate) async fn closed(&self) {
+        self.closed.cancelled().await;
+    }
+
+    /// A token cancelled once the app starts shutting down, so long work can stop early
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.token.child_token()
+    }
+
+    pub fn cancel(&self) {
+        self.token.cancel();
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.token.is_cancelled()
+    }
+
+    /// Aborts every running task, returning how many had not finished yet
+    pub fn abort_all(&self) -> usize {
+        let tasks = std::mem::take(&mut *self.running.tasks.lock().unwrap());
+        tasks.iter().filter(|(id, entry)| entry.abort(**id)).count()
     }
 }
 
@@ -67,6 +324,108 @@ mod tests {
         assert_eq!(ran.load(std::sync::atomic::Ordering::Relaxed), 2);
     }
 
+    #[tokio::test]
+    async fn lists_and_aborts_named_tasks() {
+        let tasks = Tasks::new();
+        let stuck = tasks.spawn_named("stuck", std::future::pending::<()>());
+        let answer = tasks.spawn_named("answer", async { 42 });
+        tasks.spawn(std::future::pending::<()>());
+
+        let snapshot = tasks.snapshot();
+        let names: Vec<_> = snapshot.iter().map(|task| task.name.as_deref()).collect();
+        assert_eq!(names, [Some("stuck"), Some("answer"), None]);
+        assert!(snapshot[0].origin.file().ends_with("tracker.rs"));
+        assert!(snapshot[0]
+            .to_string()
+            .starts_with("task `stuck` spawned at"));
+
+        assert_eq!(answer.join().await.unwrap(), 42);
+
+        stuck.abort();
+        assert!(stuck.join().await.unwrap_err().is_cancelled());
+
+        let unnamed = tasks.snapshot()[0].id;
+        assert!(tasks.abort(unnamed));
+        assert!(!tasks.abort(unnamed));
+
+        tasks.close();
+        tasks.wait().await;
+        assert!(tasks.snapshot().is_empty());
+    }
+
+    #[tokio::test]
+    async fn scopes_tasks_in_children() {
+        let tasks = Tasks::new();
+        let request = tasks.child_scope();
+        let nested = request.child_scope();
+
+        let token = request.cancellation_token();
+        request.spawn_named("request", async move { token.cancelled().await });
+        nested.spawn_named("nested", std::future::pending::<()>());
+        let app = tasks.spawn_named("app", std::future::pending::<()>());
+
+        let names = |tasks: &Tasks| -> Vec<_> {
+            tasks
+                .snapshot()
+                .into_iter()
+                .filter_map(|task| task.name)
+                .collect()
+        };
+        assert_eq!(names(&tasks), ["request", "nested", "app"]);
+        assert_eq!(names(&request), ["request", "nested"]);
+
+        // Cancelling the child leaves the parent alone
+        request.cancel();
+        assert!(!tasks.is_cancelled());
+        assert_eq!(nested.abort_all(), 1);
+        // Unlisted from the parents right away, not once the task is dropped
+        assert_eq!(names(&tasks), ["request", "app"]);
+
+        request.close();
+        tokio::time::timeout(Duration::from_secs(1), request.wait())
+            .await
+            .expect("the child scope should be done");
+        assert_eq!(names(&tasks), ["app"]);
+
+        // The parent still waits for tasks of its children
+        let late = nested.spawn_named("late", async {
+            tokio::time::sleep(Duration::from_millis(20)).await;
+        });
+        app.abort();
+        tasks.close();
+        tasks.wait().await;
+        assert!(late.is_finished());
+    }
+
+    #[tokio::test]
+    async fn closes_child_scopes() {
+        use crate::{router_bus::RouterBus, tasks::ShutdownSignal};
+
+        #[derive(Clone)]
+        struct MyCtx;
+        impl Context for MyCtx {}
+
+        let tasks = Tasks::new();
+        let request = tasks.child_scope();
+
+        let (_port, socket) =
+            crate::event_bus::create(8, request.clone(), ShutdownSignal::default(), None);
+        let handle =
+            RouterBus::new(socket, MyCtx, router::Router::default(), request.clone()).start();
+        assert!(handle.is_running());
+
+        tasks.close();
+        tokio::time::timeout(Duration::from_secs(1), tasks.wait())
+            .await
+            .expect("closing the parent should stop the listener");
+        assert!(!handle.is_running());
+
+        // The child scope is closed as well
+        tokio::time::timeout(Duration::from_secs(1), request.wait())
+            .await
+            .expect("the child scope should be closed");
+    }
+
     #[tokio::test(flavor = "multi_thread", worker_threads = 2)]
     async fn works_with_events_router() {
         use crate::context::CreamContext;
@@ -139,7 +498,7 @@ mod tests {
         let shutdown: Shutdown = cream_ctx.provide();
         shutdown.run().await;
 
-        assert_eq!(tasks.0.len(), 0, "there should be no tasks left");
+        assert_eq!(tasks.tracker.len(), 0, "there should be no tasks left");
 
         assert!(
             ctx.created.load(std::sync::atomic::Ordering::Relaxed),
@@ -150,4 +509,141 @@ mod tests {
             "handler should have run"
         );
     }
+
+    #[tokio::test]
+    async fn shutdown_cancels_handlers() {
+        use crate::{context::CreamContext, tasks::CancellationToken};
+
+        #[derive(Clone)]
+        struct MyCtx(CreamContext);
+
+        impl Context for MyCtx {}
+
+        impl FromContext<MyCtx> for CancellationToken {
+            fn from_context(ctx: &MyCtx) -> Self {
+                ctx.0.provide()
+            }
+        }
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(MyCtx)]
+        struct LongHandler {
+            token: CancellationToken,
+        }
+
+        impl Handler for LongHandler {
+            type Event = MyEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                self.token.cancelled().await;
+                Ok(())
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let mut router = router::Router::<MyCtx>::default();
+        router.add::<LongHandler>();
+
+        let (events_ctx, setup) = EventsContextBuilder::default().build(&cream_ctx);
+        setup.setup(router, MyCtx(cream_ctx.clone()));
+
+        let port: EventBusPort = events_ctx.provide();
+        port.publish(MyEvent);
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        tokio::time::timeout(std::time::Duration::from_secs(5), shutdown.run())
+            .await
+            .expect("handler should observe the cancellation");
+    }
+
+    #[tokio::test]
+    async fn shutdown_reports_dropped_and_aborted_work() {
+        use crate::{context::CreamContext, tasks::CancellationToken};
+
+        #[derive(Clone)]
+        struct MyCtx {
+            cream: CreamContext,
+            port: EventBusPort,
+        }
+
+        impl Context for MyCtx {}
+
+        impl FromContext<MyCtx> for CancellationToken {
+            fn from_context(ctx: &MyCtx) -> Self {
+                ctx.cream.provide()
+            }
+        }
+
+        impl FromContext<MyCtx> for EventBusPort {
+            fn from_context(ctx: &MyCtx) -> Self {
+                ctx.port.clone()
+            }
+        }
+
+        #[derive(Clone)]
+        struct MyEvent;
+        impl DomainEvent for MyEvent {
+            fn name(&self) -> &'static str {
+                "MyEvent"
+            }
+
+            fn version(&self) -> &'static str {
+                "1.0.0"
+            }
+        }
+
+        #[derive(FromContext)]
+        #[context(MyCtx)]
+        struct StubbornHandler {
+            token: CancellationToken,
+            port: EventBusPort,
+        }
+
+        impl Handler for StubbornHandler {
+            type Event = MyEvent;
+            async fn handle(&self, _: Self::Event) -> Result<(), Error> {
+                self.token.cancelled().await;
+                self.port.publish(MyEvent);
+                tokio::time::sleep(std::time::Duration::from_secs(60)).await;
+                Ok(())
+            }
+        }
+
+        let cream_ctx = CreamContext::default();
+        let mut router = router::Router::<MyCtx>::default();
+        router.add::<StubbornHandler>();
+
+        let (events_ctx, setup) = EventsContextBuilder::default().build(&cream_ctx);
+        let port: EventBusPort = events_ctx.provide();
+        setup.setup(
+            router,
+            MyCtx {
+                cream: cream_ctx.clone(),
+                port: port.clone(),
+            },
+        );
+
+        port.publish(MyEvent);
+
+        let shutdown: Shutdown = cream_ctx.provide();
+        let report = shutdown
+            .with_deadline(std::time::Duration::from_millis(100))
+            .run()
+            .await;
+
+        assert_eq!(report.dropped_events, 1);
+        assert_eq!(report.aborted_tasks, 1);
+        assert!(!report.is_clean());
+    }
 }
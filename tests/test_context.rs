@@ -0,0 +1,114 @@
+use std::sync::{
+    atomic::{AtomicUsize, Ordering},
+    Arc,
+};
+
+use cream::{
+    context::{AsyncFromContext, Context, FromContext, TestContext},
+    events::{router::Router, DomainEvent, Error, Handler},
+};
+
+trait Mailer: Send + Sync {
+    fn send(&self, to: &str);
+}
+
+struct Smtp;
+impl Mailer for Smtp {
+    fn send(&self, to: &str) {
+        panic!("sent a mail to {to} in a test");
+    }
+}
+
+#[derive(Default)]
+struct Outbox(AtomicUsize);
+impl Mailer for Outbox {
+    fn send(&self, _: &str) {
+        self.0.fetch_add(1, Ordering::SeqCst);
+    }
+}
+
+#[derive(Clone)]
+struct Mail(Arc<dyn Mailer>);
+
+#[derive(Clone, Default, Context)]
+struct AppContext;
+
+impl FromContext<AppContext> for Mail {
+    fn from_context(_: &AppContext) -> Self {
+        Mail(Arc::new(Smtp))
+    }
+}
+
+#[derive(FromContext)]
+#[context(AppContext)]
+struct Notifier {
+    mail: Mail,
+}
+
+#[derive(Clone)]
+struct Registered(&'static str);
+impl DomainEvent for Registered {
+    fn name(&self) -> &'static str {
+        "Registered"
+    }
+
+    fn version(&self) -> &'static str {
+        "1"
+    }
+}
+
+// Hand-written, so it gets its `TestContext` impl from `test_provide!`
+struct WelcomeHandler {
+    notifier: Notifier,
+}
+
+impl FromContext<AppContext> for WelcomeHandler {
+    fn from_context(ctx: &AppContext) -> Self {
+        WelcomeHandler {
+            notifier: ctx.provide(),
+        }
+    }
+}
+
+cream::test_provide!(AppContext { WelcomeHandler });
+
+impl Handler for WelcomeHandler {
+    type Event = Registered;
+
+    async fn handle(&self, Registered(user): Registered) -> Result<(), Error> {
+        self.notifier.mail.0.send(user);
+        Ok(())
+    }
+}
+
+#[derive(AsyncFromContext)]
+#[context(AppContext)]
+struct ReminderHandler {
+    notifier: Notifier,
+}
+
+impl Handler for ReminderHandler {
+    type Event = Registered;
+
+    async fn handle(&self, Registered(user): Registered) -> Result<(), Error> {
+        self.notifier.mail.0.send(user);
+        Ok(())
+    }
+}
+
+#[tokio::test]
+async fn resolves_handlers_with_overrides() {
+    let mut router = Router::<TestContext<AppContext>>::default();
+    router.set_fatal_panics(true);
+    router.add::<WelcomeHandler>();
+    router.add_async::<ReminderHandler>();
+
+    let outbox = Arc::new(Outbox::default());
+    let ctx = TestContext::new(AppContext).with_override(Mail(outbox.clone()));
+    router
+        .call(&ctx, Box::new(Registered("ada")))
+        .unwrap()
+        .await;
+
+    assert_eq!(outbox.0.load(Ordering::SeqCst), 2);
+}